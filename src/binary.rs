@@ -0,0 +1,240 @@
+use crate::error::{Error, Result};
+use crate::sink::{WalletDocument, WalletSink, SCHEMA_VERSION};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
+use std::path::Path;
+use zeroize::Zeroizing;
+
+const PUBKEY_LEN: usize = 32;
+const KEYPAIR_LEN: usize = 64;
+
+/// Size of one record: the public key, the 64-byte keypair (secret then public
+/// half) and the creation time in Unix milliseconds as a little-endian `i64`
+pub const RECORD_LEN: usize = PUBKEY_LEN + KEYPAIR_LEN + 8;
+
+fn invalid(message: String) -> Error {
+    std::io::Error::new(ErrorKind::InvalidData, message).into()
+}
+
+/// Appends found wallets to a file as fixed-size binary records, for offline runs
+/// that are imported into a database later
+pub struct BinarySink {
+    writer: BufWriter<File>,
+}
+
+impl BinarySink {
+    pub fn new(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        log::info!("Writing wallets to binary file {}", path.display());
+
+        Ok(Self {
+            writer: BufWriter::new(file),
+        })
+    }
+}
+
+/// Encode a wallet as one binary record
+fn encode(wallet: &WalletDocument) -> Result<Zeroizing<Vec<u8>>> {
+    let pubkey = bs58::decode(&wallet.public_key)
+        .into_vec()
+        .map_err(|e| invalid(format!("public key {}: {}", wallet.public_key, e)))?;
+    let keypair = Zeroizing::new(
+        bs58::decode(&wallet.private_key)
+            .into_vec()
+            .map_err(|e| invalid(format!("private key of {}: {}", wallet.public_key, e)))?,
+    );
+    if pubkey.len() != PUBKEY_LEN || keypair.len() != KEYPAIR_LEN {
+        return Err(invalid(format!(
+            "keys of {} have the wrong length",
+            wallet.public_key
+        )));
+    }
+
+    let mut record = Zeroizing::new(Vec::with_capacity(RECORD_LEN));
+    record.extend_from_slice(&pubkey);
+    record.extend_from_slice(&keypair);
+    record.extend_from_slice(&wallet.created_at.timestamp_millis().to_le_bytes());
+    Ok(record)
+}
+
+/// Decode one binary record; the pattern fields are left empty as the format doesn't store them
+fn decode(record: &[u8]) -> Result<WalletDocument> {
+    let (pubkey, rest) = record.split_at(PUBKEY_LEN);
+    let (keypair, timestamp) = rest.split_at(KEYPAIR_LEN);
+    let public_key = bs58::encode(pubkey).into_string();
+    if keypair[PUBKEY_LEN..] != *pubkey {
+        return Err(invalid(format!(
+            "record for {} holds another wallet's keypair",
+            public_key
+        )));
+    }
+
+    let millis = i64::from_le_bytes(timestamp.try_into().expect("8 timestamp bytes"));
+    let created_at = DateTime::<Utc>::from_timestamp_millis(millis)
+        .ok_or_else(|| invalid(format!("record for {} has a bad timestamp", public_key)))?;
+
+    Ok(WalletDocument {
+        schema_version: SCHEMA_VERSION,
+        public_key,
+        private_key: bs58::encode(keypair).into_string(),
+        matched_pattern: String::new(),
+        matched_condition: None,
+        vanity_score: 0,
+        found_at_attempt: None,
+        matched_len: None,
+        mnemonic: None,
+        derivation_path: None,
+        associated_token_account: None,
+        accounts: None,
+        created_at,
+    })
+}
+
+#[async_trait]
+impl WalletSink for BinarySink {
+    async fn save_wallet(&mut self, wallet: &WalletDocument) -> Result<()> {
+        self.writer.write_all(encode(wallet)?.as_ref())?;
+        // Flushed per wallet for the same reason as [`FileSink`](crate::sink::FileSink)
+        self.writer.flush()?;
+        log::info!("Wallet saved to binary file: {}", wallet.public_key);
+
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "binary file"
+    }
+}
+
+/// Streams the records of a file written by [`BinarySink`] back as wallet documents
+pub struct BinaryReader {
+    reader: BufReader<File>,
+}
+
+impl BinaryReader {
+    pub fn open(path: &Path) -> Result<Self> {
+        Ok(Self {
+            reader: BufReader::new(File::open(path)?),
+        })
+    }
+}
+
+impl Iterator for BinaryReader {
+    type Item = Result<WalletDocument>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut record = Zeroizing::new(vec![0; RECORD_LEN]);
+        let mut filled = 0;
+        while filled < RECORD_LEN {
+            match self.reader.read(&mut record[filled..]) {
+                Ok(0) => break,
+                Ok(read) => filled += read,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Some(Err(e.into())),
+            }
+        }
+
+        match filled {
+            0 => None,
+            // A run killed mid-write leaves a partial record at the end; nothing follows it
+            RECORD_LEN => Some(decode(&record)),
+            _ => {
+                log::warn!(
+                    "Ignoring a truncated record of {} bytes at the end of the file",
+                    filled
+                );
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::signature::{Keypair, Signer};
+
+    fn document(public_key: &Keypair, keypair: &Keypair, millis: i64) -> WalletDocument {
+        WalletDocument {
+            schema_version: SCHEMA_VERSION,
+            public_key: public_key.pubkey().to_string(),
+            private_key: bs58::encode(keypair.to_bytes()).into_string(),
+            matched_pattern: String::new(),
+            matched_condition: None,
+            vanity_score: 0,
+            found_at_attempt: None,
+            matched_len: None,
+            mnemonic: None,
+            derivation_path: None,
+            associated_token_account: None,
+            accounts: None,
+            created_at: DateTime::<Utc>::from_timestamp_millis(millis).unwrap(),
+        }
+    }
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("{}-{}.bin", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    #[tokio::test]
+    async fn records_round_trip_and_a_truncated_tail_is_ignored() {
+        let path = temp_path("binary-round-trip");
+        let keypairs: Vec<Keypair> = (0..5).map(|_| Keypair::new()).collect();
+        let documents: Vec<WalletDocument> = keypairs
+            .iter()
+            .enumerate()
+            .map(|(i, keypair)| document(keypair, keypair, 1_700_000_000_000 + i as i64))
+            .collect();
+
+        let mut sink = BinarySink::new(&path).unwrap();
+        for document in &documents {
+            sink.save_wallet(document).await.unwrap();
+        }
+        drop(sink);
+        assert_eq!(
+            std::fs::metadata(&path).unwrap().len(),
+            (documents.len() * RECORD_LEN) as u64
+        );
+
+        // A run killed mid-write leaves part of a record behind
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&[7; RECORD_LEN / 2]).unwrap();
+        drop(file);
+
+        let read: Vec<WalletDocument> = BinaryReader::open(&path)
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(read.len(), documents.len());
+        for (read, written) in read.iter().zip(&documents) {
+            assert_eq!(read.public_key, written.public_key);
+            assert_eq!(read.private_key, written.private_key);
+            assert_eq!(read.created_at, written.created_at);
+            assert_eq!(read.schema_version, SCHEMA_VERSION);
+        }
+    }
+
+    #[test]
+    fn a_record_holding_another_wallets_keypair_is_rejected() {
+        let (wallet, other) = (Keypair::new(), Keypair::new());
+        let mixed = document(&wallet, &other, 1_700_000_000_000);
+        let record = encode(&mixed).unwrap();
+
+        let err = decode(&record).unwrap_err();
+        assert!(
+            err.to_string().contains("another wallet's keypair"),
+            "{err}"
+        );
+        assert!(decode(&encode(&document(&wallet, &wallet, 0)).unwrap()).is_ok());
+    }
+}
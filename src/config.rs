@@ -1,7 +1,383 @@
+#[cfg(feature = "mongodb")]
+use crate::db::{WriteConcernLevel, DEFAULT_MONGODB_URI};
+use crate::error::{Error, Result};
+use crate::matcher::{Base58Alphabet, BeforeSuffix, FuzzyMatch, MatchMode};
+use crate::sink;
+use crate::writer::FullPolicy;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// Settings for a run, keyed by the same names as the CLI flags (e.g. `suffix`, `db_name`)
+/// so they can also be given in a TOML file
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct Config {
     pub threads: usize,
-    pub mongodb_uri: String,
+    /// Store wallets in MongoDB at this URI, alongside any other sinks. When no sink
+    /// is configured at all, MongoDB at [`DEFAULT_MONGODB_URI`] is used.
+    #[cfg(feature = "mongodb")]
+    pub mongodb_uri: Option<String>,
+    #[cfg(feature = "mongodb")]
     pub db_name: String,
+    #[cfg(feature = "mongodb")]
     pub collection_name: String,
-    pub suffix: String,
+    /// Append the run's start time to `collection_name` so each run gets its own collection
+    #[cfg(feature = "mongodb")]
+    pub collection_suffix_timestamp: bool,
+    #[cfg(feature = "mongodb")]
+    pub write_concern: WriteConcernLevel,
+    /// Let MongoDB delete stored wallets this many seconds after they were found
+    #[cfg(feature = "mongodb")]
+    pub ttl_seconds: Option<u64>,
+    pub output_file: Option<PathBuf>,
+    /// Gzip-compress `output_file`
+    pub compress: bool,
+    /// File found wallets are appended to as fixed-size binary records
+    pub binary_output: Option<PathBuf>,
+    pub nats_url: Option<String>,
+    /// Encrypted SQLCipher database wallets are stored in
+    pub sqlite_file: Option<PathBuf>,
+    /// Passphrase the SQLCipher database is encrypted with
+    pub sqlite_key: Option<String>,
+    pub nats_subject: String,
+    pub nats_include_private_key: bool,
+    pub suffix: Vec<String>,
+    pub fuzzy: Option<FuzzyMatch>,
+    /// Search for ever longer endings of this target instead of a fixed suffix,
+    /// keeping only finds that beat the longest so far
+    pub progressive: Option<String>,
+    /// Minimum number of address characters a fuzzy match must cover
+    pub min_match_len: Option<usize>,
+    pub before_suffix: Option<BeforeSuffix>,
+    pub prefix: Option<String>,
+    /// Match all-lowercase patterns in any case
+    pub smart_case: bool,
+    /// Whether the prefix and suffix must both match, or either one
+    pub match_mode: MatchMode,
+    pub alphabet: Base58Alphabet,
+    pub forbidden_chars: Option<String>,
+    pub required_chars: Option<String>,
+    pub min_address_len: Option<usize>,
+    pub max_address_len: Option<usize>,
+    /// Longest run of one repeated character an address may contain
+    pub max_repeat: Option<usize>,
+    /// Fewest different characters an address may contain
+    pub min_distinct: Option<usize>,
+    /// Lowest Shannon entropy, in bits per character, an address may have
+    pub min_entropy: Option<f64>,
+    /// Substrings, in any case, that reject an otherwise matching address
+    pub exclude: Vec<String>,
+    /// Log addresses that end with a suffix missing at most this many leading characters
+    pub log_near_misses: Option<usize>,
+    pub save_timeout_secs: u64,
+    /// Found wallets the writer queue holds
+    pub writer_queue_size: usize,
+    /// What workers do with a find when the writer queue is full
+    pub writer_full_policy: FullPolicy,
+    pub single: bool,
+    pub rate_ema_alpha: f64,
+    /// Log progress on this cadence instead of every 100,000 wallets
+    pub progress_every_secs: Option<u64>,
+    /// Restarts a failing worker gets before it gives up
+    pub max_consecutive_failures: u32,
+    /// Pause before a failed worker's first restart, doubled for each further failure in a row
+    pub restart_delay_ms: u64,
+    pub spill_on_db_failure: Option<PathBuf>,
+    pub replay_spill: bool,
+    /// Accounts scanned per mnemonic; `None` generates raw random keypairs
+    pub derivation_scan: Option<u32>,
+    /// Save each matching mnemonic once with all of its scanned accounts
+    pub group_by_seed: bool,
+    pub qr: bool,
+    /// Attempts each worker makes between scheduler yields
+    pub yield_every: u64,
+    /// Only accept addresses on the ed25519 curve
+    pub require_on_curve: bool,
+    /// Mint whose associated token accounts are matched instead of the public keys
+    pub match_ata: Option<String>,
+    /// Match the base58 private key instead of the address
+    pub match_private: bool,
+    /// Where the JSON run summary is written on exit
+    pub summary_file: PathBuf,
+    /// File the per-pattern find counts are rewritten to while the run goes on
+    pub count_by_pattern_file: Option<PathBuf>,
+    /// Seconds between rewrites of `count_by_pattern_file`
+    pub count_by_pattern_interval_secs: u64,
+    /// Stop the run once it has been going this long
+    #[serde(default, with = "humantime_serde")]
+    pub max_runtime: Option<std::time::Duration>,
+    /// Stop the run once this many wallets were found
+    pub count: Option<u64>,
+    /// Cap on the combined wallets generated per second
+    pub max_rate: Option<f64>,
+    /// Port the live stats dashboard is served on
+    #[cfg(feature = "dashboard")]
+    pub dashboard_port: Option<u16>,
+    /// Program run with the public key of each found wallet
+    pub on_found: Option<PathBuf>,
+    /// Also write the private key to the `on_found` program's stdin
+    pub on_found_private_key: bool,
+    /// Print each found wallet to stdout as a tab-separated line
+    pub emit_stdout: bool,
+    /// With `emit_stdout`, print only the public key
+    pub pubkey_only: bool,
+    /// Keep private keys and mnemonics out of the logs
+    pub no_log_secrets: bool,
+    /// Refuse to fall back to the default suffix when no pattern is given
+    pub require_explicit_pattern: bool,
+}
+
+impl Config {
+    /// Whether any sink other than MongoDB is configured
+    pub fn has_other_sink(&self) -> bool {
+        self.output_file.is_some()
+            || self.binary_output.is_some()
+            || self.nats_url.is_some()
+            || self.sqlite_file.is_some()
+    }
+
+    /// The JSON-lines file wallets are appended to, with ".gz" added when compressing
+    pub fn output_path(&self) -> Option<PathBuf> {
+        let path = self.output_file.as_deref()?;
+        Some(if self.compress {
+            sink::gzip_path(path)
+        } else {
+            path.to_path_buf()
+        })
+    }
+
+    /// Whether found wallets go to MongoDB: a URI was given, or there's no other sink
+    #[cfg(feature = "mongodb")]
+    pub fn uses_mongodb(&self) -> bool {
+        self.mongodb_uri.is_some() || !self.has_other_sink()
+    }
+
+    /// The MongoDB connection string, falling back to [`DEFAULT_MONGODB_URI`]
+    #[cfg(feature = "mongodb")]
+    pub fn mongodb_uri(&self) -> &str {
+        self.mongodb_uri.as_deref().unwrap_or(DEFAULT_MONGODB_URI)
+    }
+
+    /// Fill in settings from a TOML file. Keys for which `is_explicit` returns true
+    /// were given on the command line or in the environment and keep their value.
+    pub fn merge_file(self, path: &Path, is_explicit: impl Fn(&str) -> bool) -> Result<Self> {
+        let contents = std::fs::read_to_string(path).map_err(|e| {
+            Error::Config(format!(
+                "Failed to read config file {}: {}",
+                path.display(),
+                e
+            ))
+        })?;
+        let file: toml::Table = toml::from_str(&contents).map_err(|e| {
+            Error::Config(format!(
+                "Failed to parse config file {}: {}",
+                path.display(),
+                e
+            ))
+        })?;
+
+        // Round-trip through a table so the file only has to list the settings it changes
+        let defaults = toml::to_string(&self).map_err(|e| Error::Config(e.to_string()))?;
+        let mut merged: toml::Table =
+            toml::from_str(&defaults).map_err(|e| Error::Config(e.to_string()))?;
+        for (key, value) in file {
+            if !is_explicit(&key) {
+                merged.insert(key, value);
+            }
+        }
+
+        merged.try_into().map_err(|e| {
+            Error::Config(format!(
+                "Invalid setting in config file {}: {}",
+                path.display(),
+                e
+            ))
+        })
+    }
+}
+
+/// Whether the config-file setting `key` was given as flags instead, going by the
+/// IDs of the flags set on the command line or in the environment. Some settings
+/// are built from more than one flag.
+pub fn set_by_flags(key: &str, explicit_args: &HashSet<String>) -> bool {
+    match key {
+        "fuzzy" => explicit_args.contains("fuzzy") || explicit_args.contains("max_distance"),
+        "match_mode" => explicit_args.contains("any_of"),
+        "before_suffix" => {
+            explicit_args.contains("before_suffix_chars")
+                || explicit_args.contains("before_suffix_len")
+        }
+        "derivation_scan" => {
+            explicit_args.contains("mnemonic") || explicit_args.contains("derivation_scan")
+        }
+        key => explicit_args.contains(key),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Settings as the command line builds them before a config file is merged in
+    fn cli_config() -> Config {
+        Config {
+            threads: 8,
+            #[cfg(feature = "mongodb")]
+            mongodb_uri: None,
+            #[cfg(feature = "mongodb")]
+            db_name: "vanity_wallets".to_string(),
+            #[cfg(feature = "mongodb")]
+            collection_name: "wallets".to_string(),
+            #[cfg(feature = "mongodb")]
+            collection_suffix_timestamp: false,
+            #[cfg(feature = "mongodb")]
+            write_concern: WriteConcernLevel::Majority,
+            #[cfg(feature = "mongodb")]
+            ttl_seconds: None,
+            output_file: None,
+            compress: false,
+            binary_output: None,
+            nats_url: None,
+            sqlite_file: None,
+            sqlite_key: None,
+            nats_subject: "vanity.found".to_string(),
+            nats_include_private_key: false,
+            suffix: vec!["pump".to_string()],
+            fuzzy: None,
+            progressive: None,
+            min_match_len: None,
+            before_suffix: None,
+            prefix: None,
+            smart_case: false,
+            match_mode: MatchMode::All,
+            alphabet: Base58Alphabet::Bitcoin,
+            forbidden_chars: None,
+            required_chars: None,
+            min_address_len: None,
+            max_address_len: None,
+            max_repeat: None,
+            min_distinct: None,
+            min_entropy: None,
+            exclude: Vec::new(),
+            log_near_misses: None,
+            save_timeout_secs: 10,
+            writer_queue_size: 1024,
+            writer_full_policy: FullPolicy::Block,
+            single: false,
+            rate_ema_alpha: 0.3,
+            progress_every_secs: None,
+            max_consecutive_failures: 5,
+            restart_delay_ms: 1000,
+            spill_on_db_failure: None,
+            replay_spill: false,
+            derivation_scan: None,
+            group_by_seed: false,
+            qr: false,
+            yield_every: 1000,
+            require_on_curve: false,
+            match_ata: None,
+            match_private: false,
+            summary_file: PathBuf::from("run-summary.json"),
+            count_by_pattern_file: None,
+            count_by_pattern_interval_secs: 10,
+            max_runtime: None,
+            count: None,
+            max_rate: None,
+            #[cfg(feature = "dashboard")]
+            dashboard_port: None,
+            on_found: None,
+            on_found_private_key: false,
+            emit_stdout: false,
+            pubkey_only: false,
+            no_log_secrets: false,
+            require_explicit_pattern: false,
+        }
+    }
+
+    fn write_file(name: &str, contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("{}-{}.toml", name, std::process::id()));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn flags(ids: &[&str]) -> HashSet<String> {
+        ids.iter().map(|id| id.to_string()).collect()
+    }
+
+    #[test]
+    fn merge_file_fills_in_settings_the_flags_left_unset() {
+        let path = write_file(
+            "vanity-config-merge",
+            r#"
+                threads = 2
+                suffix = ["moon", "sun"]
+                smart_case = true
+                match_mode = "any"
+                prefix = "ab"
+                derivation_scan = 4
+                max_runtime = "1h 30m"
+                output_file = "found.jsonl"
+
+                [fuzzy]
+                target = "pump"
+                max_distance = 1
+            "#,
+        );
+
+        // --threads and --max-distance were given, so they win over the file
+        let explicit = flags(&["threads", "max_distance"]);
+        let config = cli_config()
+            .merge_file(&path, |key| set_by_flags(key, &explicit))
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(config.threads, 8);
+        assert!(config.fuzzy.is_none());
+        assert_eq!(config.suffix, ["moon", "sun"]);
+        assert!(config.smart_case);
+        assert_eq!(config.match_mode, MatchMode::Any);
+        assert_eq!(config.prefix.as_deref(), Some("ab"));
+        assert_eq!(config.derivation_scan, Some(4));
+        assert_eq!(
+            config.max_runtime,
+            Some(std::time::Duration::from_secs(90 * 60))
+        );
+        assert_eq!(config.output_file, Some(PathBuf::from("found.jsonl")));
+        // Settings the file doesn't mention keep what the command line built
+        assert_eq!(config.yield_every, 1000);
+        assert_eq!(config.summary_file, PathBuf::from("run-summary.json"));
+    }
+
+    #[test]
+    fn settings_built_from_several_flags_are_explicit_if_any_of_them_is() {
+        assert!(set_by_flags("fuzzy", &flags(&["max_distance"])));
+        assert!(set_by_flags("match_mode", &flags(&["any_of"])));
+        assert!(set_by_flags(
+            "before_suffix",
+            &flags(&["before_suffix_len"])
+        ));
+        assert!(set_by_flags("derivation_scan", &flags(&["mnemonic"])));
+        assert!(set_by_flags("suffix", &flags(&["suffix"])));
+        assert!(!set_by_flags("match_mode", &flags(&["match_mode"])));
+        assert!(!set_by_flags("prefix", &flags(&["suffix"])));
+    }
+
+    #[test]
+    fn merge_file_rejects_unknown_and_mistyped_settings() {
+        for (name, contents) in [
+            ("vanity-config-unknown", "sufix = [\"pump\"]"),
+            ("vanity-config-mistyped", "threads = \"many\""),
+            ("vanity-config-syntax", "threads = "),
+        ] {
+            let path = write_file(name, contents);
+            let result = cli_config().merge_file(&path, |_| false);
+            std::fs::remove_file(&path).unwrap();
+            assert!(
+                matches!(result, Err(Error::Config(_))),
+                "{contents}: {:?}",
+                result.err()
+            );
+        }
+    }
 }
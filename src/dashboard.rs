@@ -0,0 +1,30 @@
+use crate::error::Result;
+use crate::stats::LiveStats;
+use axum::response::Html;
+use axum::routing::get;
+use axum::{Json, Router};
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+/// The page served at `/`; it polls `/stats` and renders the numbers
+const PAGE: &str = include_str!("dashboard.html");
+
+/// Serve the dashboard on every interface at `port` until the process exits.
+/// `stats` is called for each poll and served as JSON at `/stats`, so it should
+/// only read counters.
+pub async fn serve<F>(port: u16, stats: F) -> Result<()>
+where
+    F: Fn() -> LiveStats + Send + Sync + 'static,
+{
+    let stats = Arc::new(stats);
+    let app = Router::new()
+        .route("/", get(|| async { Html(PAGE) }))
+        .route("/stats", get(move || async move { Json(stats()) }));
+
+    let address = SocketAddr::from(([0, 0, 0, 0], port));
+    let listener = tokio::net::TcpListener::bind(address).await?;
+    log::info!("Dashboard listening on http://{}", address);
+    axum::serve(listener, app).await?;
+
+    Ok(())
+}
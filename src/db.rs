@@ -1,24 +1,260 @@
+use crate::error::{Error, Result};
+use crate::sink::{WalletDocument, WalletSink};
 use crate::wallet_generator::WalletGenerator;
-use anyhow::Result;
-use chrono::Utc;
-use mongodb::bson::doc;
-use mongodb::{options::ClientOptions, Client, Collection};
+use async_trait::async_trait;
+use futures::TryStreamExt;
+use mongodb::bson::{doc, Bson, DateTime, Document};
+use mongodb::error::{ErrorKind, WriteFailure};
+use mongodb::options::{
+    Acknowledgment, ClientOptions, FindOptions, IndexOptions, UpdateOptions, WriteConcern,
+};
+use mongodb::{Client, Collection, IndexModel};
 use serde::{Deserialize, Serialize};
-use solana_sdk::signature::{Keypair, Signer};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::Duration;
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct WalletDocument {
-    pub public_key: String,
-    pub private_key: String,
-    pub created_at: chrono::DateTime<chrono::Utc>,
+/// BSON date copy of `created_at` written when a TTL is set. `created_at` itself is
+/// stored as an RFC 3339 string, which TTL indexes ignore.
+const TTL_FIELD: &str = "created_at_date";
+
+/// Server used when no MongoDB connection string is given
+pub const DEFAULT_MONGODB_URI: &str = "mongodb://localhost:27017";
+
+/// Largest document MongoDB stores
+pub const MAX_BSON_SIZE: usize = 16 * 1024 * 1024;
+
+/// Numbers the documents of a wallet split by [`split_document`], from 0. Wallets
+/// stored whole have none.
+pub const PART_FIELD: &str = "part";
+
+/// Narrow `filter` to one document per wallet: those stored whole and the first
+/// part of split ones, which [`MongoDBClient`]'s readers count and return
+fn first_parts(mut filter: Document) -> Document {
+    filter.insert(PART_FIELD, doc! {"$in": [Bson::Null, 0]});
+    filter
+}
+
+/// File formats supported by [`MongoDBClient::export`]
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+}
+
+/// Outcome of [`MongoDBClient::verify`]
+#[derive(Debug, Default)]
+pub struct VerifyReport {
+    pub verified: u64,
+    pub failed: u64,
+}
+
+/// Write acknowledgment required before a save counts as successful
+#[derive(Clone, Copy, Debug, clap::ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WriteConcernLevel {
+    /// Acknowledged by the primary only
+    #[value(name = "1")]
+    #[serde(rename = "1")]
+    One,
+    /// Acknowledged by a majority of the replica set
+    Majority,
+}
+
+impl WriteConcernLevel {
+    fn to_write_concern(self) -> WriteConcern {
+        let acknowledgment = match self {
+            Self::One => Acknowledgment::Nodes(1),
+            Self::Majority => Acknowledgment::Majority,
+        };
+        WriteConcern::builder().w(acknowledgment).build()
+    }
+}
+
+/// How a failed insert should be handled
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InsertErrorKind {
+    /// Network errors, elections, timeouts and anything unrecognized: worth retrying
+    Transient,
+    /// The wallet is already stored, e.g. an earlier attempt succeeded but timed out
+    DuplicateKey,
+    /// The collection's validator rejected the document
+    ValidationFailed,
+    /// The document exceeds the maximum BSON size
+    DocumentTooLarge,
+    /// The deployment is out of disk space or over its storage quota
+    QuotaExceeded,
+    /// The configured user may not insert into the collection
+    Unauthorized,
+}
+
+impl InsertErrorKind {
+    fn description(self) -> &'static str {
+        match self {
+            Self::Transient => "transient MongoDB error",
+            Self::DuplicateKey => "wallet is already stored",
+            Self::ValidationFailed => "document rejected by the collection's validator",
+            Self::DocumentTooLarge => "document exceeds the maximum BSON size",
+            Self::QuotaExceeded => "MongoDB is out of disk space or over its storage quota",
+            Self::Unauthorized => "not authorized to insert into the collection",
+        }
+    }
+}
+
+/// Classify an insert error by its server error code, so permanent failures
+/// aren't retried like network errors
+pub fn classify_insert_error(error: &mongodb::error::Error) -> InsertErrorKind {
+    let (code, message) = match error.kind.as_ref() {
+        ErrorKind::Authentication { .. } => return InsertErrorKind::Unauthorized,
+        ErrorKind::Command(e) => (e.code, e.message.as_str()),
+        ErrorKind::Write(WriteFailure::WriteError(e)) => (e.code, e.message.as_str()),
+        ErrorKind::Write(WriteFailure::WriteConcernError(e)) => (e.code, e.message.as_str()),
+        _ => return InsertErrorKind::Transient,
+    };
+
+    match code {
+        11000 | 11001 | 12582 => InsertErrorKind::DuplicateKey,
+        121 => InsertErrorKind::ValidationFailed,
+        10334 | 17280 => InsertErrorKind::DocumentTooLarge,
+        12501 | 14031 => InsertErrorKind::QuotaExceeded,
+        // Atlas reports quota violations with its generic error code
+        8000 if message.to_lowercase().contains("quota") => InsertErrorKind::QuotaExceeded,
+        13 | 18 => InsertErrorKind::Unauthorized,
+        _ => InsertErrorKind::Transient,
+    }
+}
+
+fn bson_len(document: &Document) -> Result<usize> {
+    Ok(mongodb::bson::to_vec(document)?.len())
+}
+
+/// Split a wallet's document into parts of at most `max_size` bytes. Only the
+/// `accounts` of a `--group-by-seed` document can grow that large, so each part is
+/// a copy of the wallet with the next batch of accounts, numbered in [`PART_FIELD`].
+/// Documents that fit, or that are too large without their accounts, are returned
+/// whole. [`reassemble`] puts the parts back together.
+///
+/// ```
+/// use mongodb::bson::{doc, Bson};
+/// use solana_vanity_wallet::db::{reassemble, split_document, MAX_BSON_SIZE};
+///
+/// let account = doc! {"derivation_path": "m/44'/501'/0'/0'", "private_key": "k".repeat(88)};
+/// let accounts: Vec<Bson> = (0..150_000).map(|_| account.clone().into()).collect();
+/// let document = doc! {"public_key": "AbCpump", "accounts": accounts};
+///
+/// let parts = split_document(document, MAX_BSON_SIZE).unwrap();
+/// assert!(parts.len() > 1);
+/// let mut total = 0;
+/// for (i, part) in parts.iter().enumerate() {
+///     assert!(mongodb::bson::to_vec(part).unwrap().len() <= MAX_BSON_SIZE);
+///     assert_eq!(part.get_str("public_key").unwrap(), "AbCpump");
+///     assert_eq!(part.get_i32("part").unwrap(), i as i32);
+///     total += part.get_array("accounts").unwrap().len();
+/// }
+/// assert_eq!(total, 150_000);
+/// assert_eq!(reassemble(parts).get_array("accounts").unwrap().len(), 150_000);
+/// ```
+pub fn split_document(mut document: Document, max_size: usize) -> Result<Vec<Document>> {
+    if bson_len(&document)? <= max_size {
+        return Ok(vec![document]);
+    }
+    let Some(Bson::Array(accounts)) = document.remove("accounts") else {
+        return Ok(vec![document]);
+    };
+
+    // Sized with an empty array and the part number every part carries
+    let mut base = document.clone();
+    base.insert("accounts", Bson::Array(Vec::new()));
+    base.insert(PART_FIELD, 0);
+    let base_len = bson_len(&base)?;
+
+    let mut batches = vec![Vec::new()];
+    let mut batch_len = base_len;
+    for account in accounts {
+        let batch = batches.last_mut().expect("there is always a batch");
+        // An array element is its index as a key plus the value; the wrapping
+        // document's 4-byte length and terminator aren't part of it
+        let len = bson_len(&doc! {batch.len().to_string(): account.clone()})? - 5;
+        if !batch.is_empty() && batch_len + len > max_size {
+            batches.push(vec![account]);
+            batch_len = base_len + len;
+        } else {
+            batch.push(account);
+            batch_len += len;
+        }
+    }
+
+    if batches.len() == 1 {
+        document.insert("accounts", Bson::Array(batches.remove(0)));
+        return Ok(vec![document]);
+    }
+    Ok(batches
+        .into_iter()
+        .enumerate()
+        .map(|(part, batch)| {
+            let mut part_document = document.clone();
+            part_document.insert("accounts", Bson::Array(batch));
+            part_document.insert(PART_FIELD, part as i32);
+            part_document
+        })
+        .collect())
+}
+
+/// Join the parts [`split_document`] made of a wallet back into one document, in
+/// any order. A single document stored whole comes back unchanged.
+pub fn reassemble(mut parts: Vec<Document>) -> Document {
+    parts.sort_by_key(|part| part.get_i32(PART_FIELD).unwrap_or(0));
+    let mut parts = parts.into_iter();
+    let mut document = parts.next().unwrap_or_default();
+    for mut part in parts {
+        if let (Ok(accounts), Ok(more)) = (
+            document.get_array_mut("accounts"),
+            part.get_array_mut("accounts"),
+        ) {
+            accounts.append(more);
+        }
+    }
+    document.remove(PART_FIELD);
+    document
+}
+
+/// One row of a CSV export. Only the fields every document has, so rows from
+/// different modes and schema versions all share the same columns.
+#[derive(Serialize)]
+struct CsvRow<'a> {
+    public_key: &'a str,
+    private_key: &'a str,
+    matched_pattern: &'a str,
+    created_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl<'a> From<&'a WalletDocument> for CsvRow<'a> {
+    fn from(wallet: &'a WalletDocument) -> Self {
+        Self {
+            public_key: &wallet.public_key,
+            private_key: &wallet.private_key,
+            matched_pattern: &wallet.matched_pattern,
+            created_at: wallet.created_at,
+        }
+    }
 }
 
 pub struct MongoDBClient {
     collection: Collection<WalletDocument>,
+    update_options: UpdateOptions,
+    /// Whether saves add the [`TTL_FIELD`] the expiry index watches
+    expires: bool,
 }
 
 impl MongoDBClient {
-    pub async fn new(uri: &str, db_name: &str, collection_name: &str) -> Result<Self> {
+    pub async fn new(
+        uri: &str,
+        db_name: &str,
+        collection_name: &str,
+        write_concern: WriteConcernLevel,
+        ttl: Option<Duration>,
+    ) -> Result<Self> {
         // Parse a connection string into an options struct
         let client_options = ClientOptions::parse(uri).await?;
 
@@ -29,7 +265,8 @@ impl MongoDBClient {
         client
             .database("admin")
             .run_command(doc! {"ping": 1}, None)
-            .await?;
+            .await
+            .map_err(|e| Error::Connect(format!("Could not reach MongoDB: {}", e)))?;
 
         log::info!("Connected to MongoDB!");
 
@@ -37,30 +274,467 @@ impl MongoDBClient {
         let db = client.database(db_name);
         let collection = db.collection::<WalletDocument>(collection_name);
 
-        Ok(Self { collection })
+        log::info!("Using MongoDB write concern: {:?}", write_concern);
+        let update_options = UpdateOptions::builder()
+            .upsert(true)
+            .write_concern(write_concern.to_write_concern())
+            .build();
+
+        if let Some(ttl) = ttl {
+            // Fails if the collection already has a TTL index with another expiry
+            let index = IndexModel::builder()
+                .keys(doc! {TTL_FIELD: 1})
+                .options(IndexOptions::builder().expire_after(ttl).build())
+                .build();
+            collection.create_index(index, None).await?;
+            log::warn!(
+                "Wallets in {} are deleted {} seconds after they are found; don't use this collection for real funds",
+                collection_name,
+                ttl.as_secs()
+            );
+        }
+
+        Ok(Self {
+            collection,
+            update_options,
+            expires: ttl.is_some(),
+        })
     }
 
-    pub async fn save_wallet(&self, keypair: &Keypair) -> Result<()> {
-        let public_key = keypair.pubkey().to_string();
-        let private_key = WalletGenerator::get_private_key_string(keypair);
-        let created_at = chrono::Utc::now();
+    pub async fn get_wallet_count(&self) -> Result<u64> {
+        let count = self
+            .collection
+            .count_documents(first_parts(Document::new()), None)
+            .await?;
+        Ok(count)
+    }
 
-        log::debug!("Creating wallet document for public key: {}", public_key);
-        let wallet_doc = WalletDocument {
-            public_key: public_key.clone(),
-            private_key,
-            created_at,
+    /// Fetch up to `limit` stored wallets found with the given pattern, newest first
+    pub async fn list_by_pattern(&self, pattern: &str, limit: i64) -> Result<Vec<WalletDocument>> {
+        let options = FindOptions::builder()
+            .sort(doc! {"created_at": -1})
+            .limit(limit)
+            .build();
+        let mut cursor = self
+            .documents()
+            .find(first_parts(doc! {"matched_pattern": pattern}), options)
+            .await?;
+        let mut wallets = Vec::new();
+        while let Some(document) = cursor.try_next().await? {
+            wallets.push(self.whole_wallet(document).await?);
+        }
+        Ok(wallets)
+    }
+
+    /// Count the stored wallets found with the given pattern
+    pub async fn count_by_pattern(&self, pattern: &str) -> Result<u64> {
+        let count = self
+            .collection
+            .count_documents(first_parts(doc! {"matched_pattern": pattern}), None)
+            .await?;
+        Ok(count)
+    }
+
+    /// Delete every stored wallet found with the given pattern, returning how many were removed
+    pub async fn delete_by_pattern(&self, pattern: &str) -> Result<u64> {
+        // Later parts go first so an interrupted delete never strands them without a first part
+        self.collection
+            .delete_many(
+                doc! {"matched_pattern": pattern, PART_FIELD: {"$gt": 0}},
+                None,
+            )
+            .await?;
+        let result = self
+            .collection
+            .delete_many(doc! {"matched_pattern": pattern}, None)
+            .await?;
+        log::info!(
+            "Deleted {} wallets matching pattern '{}'",
+            result.deleted_count,
+            pattern
+        );
+        Ok(result.deleted_count)
+    }
+
+    /// Stream every stored wallet into a CSV or JSON file, returning how many were written
+    pub async fn export(&self, format: ExportFormat, path: &Path) -> Result<u64> {
+        let mut cursor = self
+            .documents()
+            .find(first_parts(Document::new()), None)
+            .await?;
+        let mut writer = BufWriter::new(File::create(path)?);
+        let mut exported = 0;
+
+        match format {
+            ExportFormat::Csv => {
+                let mut csv_writer = csv::Writer::from_writer(writer);
+                while let Some(document) = cursor.try_next().await? {
+                    let wallet = self.whole_wallet(document).await?;
+                    csv_writer.serialize(CsvRow::from(&wallet))?;
+                    exported += 1;
+                }
+                csv_writer.flush()?;
+            }
+            ExportFormat::Json => {
+                // Write the array incrementally so large collections never sit in memory
+                writer.write_all(b"[")?;
+                while let Some(document) = cursor.try_next().await? {
+                    let wallet = self.whole_wallet(document).await?;
+                    if exported > 0 {
+                        writer.write_all(b",")?;
+                    }
+                    writer.write_all(b"\n  ")?;
+                    serde_json::to_writer(&mut writer, &wallet)?;
+                    exported += 1;
+                }
+                writer.write_all(b"\n]\n")?;
+                writer.flush()?;
+            }
+        }
+
+        log::info!("Exported {} wallets to {}", exported, path.display());
+        Ok(exported)
+    }
+
+    /// Stream every stored wallet and check that its private key re-derives its public
+    /// key, logging each record that doesn't
+    pub async fn verify(&self) -> Result<VerifyReport> {
+        // Read raw documents so a record with missing or mistyped fields is reported
+        // instead of ending the scan. Parts repeat their wallet's keys, so one is enough.
+        let mut cursor = self
+            .documents()
+            .find(first_parts(Document::new()), None)
+            .await?;
+        let mut report = VerifyReport::default();
+
+        while let Some(document) = cursor.try_next().await? {
+            let public_key = document.get_str("public_key").ok();
+            let result = match (public_key, document.get_str("private_key").ok()) {
+                (Some(public_key), Some(private_key)) => {
+                    WalletGenerator::verify_private_key(public_key, private_key)
+                }
+                _ => Err("public_key or private_key is missing"),
+            };
+
+            match (result, public_key) {
+                (Ok(()), _) => report.verified += 1,
+                (Err(reason), Some(public_key)) => {
+                    log::error!("Wallet {} failed verification: {}", public_key, reason);
+                    report.failed += 1;
+                }
+                (Err(reason), None) => {
+                    let id = document.get("_id").map(ToString::to_string);
+                    log::error!(
+                        "Document {} failed verification: {}",
+                        id.unwrap_or_default(),
+                        reason
+                    );
+                    report.failed += 1;
+                }
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Count the stored wallets at each schema version, oldest first. Records saved
+    /// before the version was recorded count as version 0.
+    pub async fn schema_versions(&self) -> Result<Vec<(u32, u64)>> {
+        let pipeline = [
+            doc! {"$match": first_parts(Document::new())},
+            doc! {"$group": {"_id": "$schema_version", "count": {"$sum": 1}}},
+            doc! {"$sort": {"_id": 1}},
+        ];
+        let mut cursor = self.collection.aggregate(pipeline, None).await?;
+        let mut versions = Vec::new();
+
+        while let Some(group) = cursor.try_next().await? {
+            let version = match group.get("_id") {
+                Some(Bson::Int32(version)) => *version as u32,
+                Some(Bson::Int64(version)) => *version as u32,
+                _ => 0,
+            };
+            let count = match group.get("count") {
+                Some(Bson::Int32(count)) => *count as u64,
+                Some(Bson::Int64(count)) => *count as u64,
+                _ => 0,
+            };
+            versions.push((version, count));
+        }
+
+        Ok(versions)
+    }
+
+    /// The collection read as raw documents
+    fn documents(&self) -> Collection<Document> {
+        self.collection.clone_with_type()
+    }
+
+    /// The wallet a stored document is the first part of, with the accounts of its
+    /// other parts if it was split
+    async fn whole_wallet(&self, document: Document) -> Result<WalletDocument> {
+        let document = match document.get_str("public_key") {
+            Ok(public_key) if document.contains_key(PART_FIELD) => {
+                let filter = doc! {"public_key": public_key, PART_FIELD: {"$gt": 0}};
+                let mut parts: Vec<Document> = self
+                    .documents()
+                    .find(filter, None)
+                    .await?
+                    .try_collect()
+                    .await?;
+                parts.push(document);
+                reassemble(parts)
+            }
+            _ => document,
         };
+        Ok(mongodb::bson::from_document(document).map_err(mongodb::error::Error::from)?)
+    }
+
+    /// Insert `document` unless one matching `filter` is stored, returning the new
+    /// document's ID, or `None` if it was already there
+    async fn upsert(&self, filter: Document, document: Document) -> Result<Option<Bson>> {
+        match self
+            .collection
+            .update_one(
+                filter,
+                doc! {"$setOnInsert": document},
+                self.update_options.clone(),
+            )
+            .await
+        {
+            Ok(result) => Ok(result.upserted_id),
+            Err(e) => match classify_insert_error(&e) {
+                InsertErrorKind::Transient => Err(e.into()),
+                InsertErrorKind::DuplicateKey => Ok(None),
+                kind => Err(Error::PermanentSave {
+                    reason: kind.description(),
+                    source: Box::new(e.into()),
+                }),
+            },
+        }
+    }
+}
+
+#[async_trait]
+impl WalletSink for MongoDBClient {
+    async fn save_wallet(&mut self, wallet: &WalletDocument) -> Result<()> {
+        log::debug!("Upserting wallet document into MongoDB");
+        // Upserting on the public key makes saves idempotent: a retry after a save that
+        // actually succeeded, or a spill replay, never stores the wallet twice
+        let mut document = mongodb::bson::to_document(wallet)?;
+        if self.expires {
+            document.insert(
+                TTL_FIELD,
+                DateTime::from_millis(wallet.created_at.timestamp_millis()),
+            );
+        }
 
-        log::debug!("Inserting wallet document into MongoDB");
-        let result = self.collection.insert_one(wallet_doc, None).await?;
-        log::info!("Wallet saved to MongoDB with ID: {}", result.inserted_id);
+        let parts = split_document(document, MAX_BSON_SIZE)?;
+        if parts.len() > 1 {
+            log::warn!(
+                "Wallet {} with {} accounts is over MongoDB's {} MB document limit; saving it as {} documents",
+                wallet.public_key,
+                wallet.accounts.as_ref().map_or(0, Vec::len),
+                MAX_BSON_SIZE / (1024 * 1024),
+                parts.len()
+            );
+        }
+
+        // Parts are saved in order, so a retry skips the ones already stored
+        for (part, document) in parts.into_iter().enumerate() {
+            let filter = match part {
+                0 => first_parts(doc! {"public_key": &wallet.public_key}),
+                _ => doc! {"public_key": &wallet.public_key, PART_FIELD: part as i32},
+            };
+            match (self.upsert(filter, document).await?, part) {
+                (Some(id), 0) => log::info!("Wallet saved to MongoDB with ID: {}", id),
+                (Some(id), _) => log::info!(
+                    "Part {} of wallet {} saved to MongoDB with ID: {}",
+                    part,
+                    wallet.public_key,
+                    id
+                ),
+                (None, 0) => {
+                    log::warn!("Wallet {} is already stored in MongoDB", wallet.public_key)
+                }
+                (None, _) => log::warn!(
+                    "Part {} of wallet {} is already stored in MongoDB",
+                    part,
+                    wallet.public_key
+                ),
+            }
+        }
 
         Ok(())
     }
 
-    pub async fn get_wallet_count(&self) -> Result<u64> {
-        let count = self.collection.count_documents(None, None).await?;
-        Ok(count)
+    async fn contains(&mut self, public_key: &str) -> Result<bool> {
+        let count = self
+            .collection
+            .count_documents(doc! {"public_key": public_key}, None)
+            .await?;
+        Ok(count > 0)
+    }
+
+    fn name(&self) -> &'static str {
+        "MongoDB"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wallet(document: serde_json::Value) -> WalletDocument {
+        serde_json::from_value(document).unwrap()
+    }
+
+    #[test]
+    fn csv_rows_round_trip_for_mixed_documents() {
+        let wallets = [
+            wallet(serde_json::json!({
+                "public_key": "AbCpump",
+                "private_key": "secret1",
+                "matched_pattern": "pump",
+                "found_at_attempt": 42,
+                "created_at": "2024-01-01T00:00:00Z",
+            })),
+            // Mnemonic finds carry optional and nested fields the first one lacks
+            wallet(serde_json::json!({
+                "public_key": "XyZmoon",
+                "private_key": "secret2",
+                "matched_pattern": "moon",
+                "mnemonic": "abandon abandon ability",
+                "derivation_path": "m/44'/501'/0'/0'",
+                "accounts": [{
+                    "derivation_path": "m/44'/501'/0'/0'",
+                    "public_key": "XyZmoon",
+                    "private_key": "secret2",
+                }],
+                "created_at": "2024-01-02T03:04:05Z",
+            })),
+        ];
+
+        let mut csv_writer = csv::Writer::from_writer(Vec::new());
+        for wallet in &wallets {
+            csv_writer.serialize(CsvRow::from(wallet)).unwrap();
+        }
+        let csv = csv_writer.into_inner().unwrap();
+
+        let mut reader = csv::Reader::from_reader(csv.as_slice());
+        assert_eq!(
+            reader.headers().unwrap(),
+            vec!["public_key", "private_key", "matched_pattern", "created_at"]
+        );
+        let rows: Vec<(String, String, String, chrono::DateTime<chrono::Utc>)> =
+            reader.deserialize().collect::<csv::Result<_>>().unwrap();
+        assert_eq!(rows.len(), wallets.len());
+        for (row, wallet) in rows.iter().zip(&wallets) {
+            assert_eq!(row.0, wallet.public_key);
+            assert_eq!(row.1, wallet.private_key);
+            assert_eq!(row.2, wallet.matched_pattern);
+            assert_eq!(row.3, wallet.created_at);
+        }
+    }
+
+    fn write_error(code: i32, message: &str) -> mongodb::error::Error {
+        let error = mongodb::bson::from_document(doc! {"code": code, "errmsg": message}).unwrap();
+        ErrorKind::Write(WriteFailure::WriteError(error)).into()
+    }
+
+    fn command_error(code: i32, message: &str) -> mongodb::error::Error {
+        let error = mongodb::bson::from_document(doc! {"code": code, "errmsg": message}).unwrap();
+        ErrorKind::Command(error).into()
+    }
+
+    #[test]
+    fn insert_errors_are_classified_by_server_code() {
+        let cases = [
+            (
+                write_error(11000, "E11000 duplicate key"),
+                InsertErrorKind::DuplicateKey,
+            ),
+            (
+                write_error(11001, "duplicate key"),
+                InsertErrorKind::DuplicateKey,
+            ),
+            (
+                write_error(12582, "duplicate key"),
+                InsertErrorKind::DuplicateKey,
+            ),
+            (
+                write_error(121, "Document failed validation"),
+                InsertErrorKind::ValidationFailed,
+            ),
+            (
+                command_error(10334, "too large"),
+                InsertErrorKind::DocumentTooLarge,
+            ),
+            (
+                write_error(12501, "quota exceeded"),
+                InsertErrorKind::QuotaExceeded,
+            ),
+            (
+                command_error(8000, "you are over your space Quota"),
+                InsertErrorKind::QuotaExceeded,
+            ),
+            (
+                command_error(8000, "AtlasError"),
+                InsertErrorKind::Transient,
+            ),
+            (
+                command_error(13, "not authorized"),
+                InsertErrorKind::Unauthorized,
+            ),
+            (
+                command_error(18, "Authentication failed"),
+                InsertErrorKind::Unauthorized,
+            ),
+            (
+                command_error(91, "shutdown in progress"),
+                InsertErrorKind::Transient,
+            ),
+        ];
+        for (error, expected) in cases {
+            assert_eq!(classify_insert_error(&error), expected, "{error}");
+        }
+    }
+
+    #[test]
+    fn network_errors_are_transient() {
+        for kind in [
+            std::io::ErrorKind::ConnectionRefused,
+            std::io::ErrorKind::TimedOut,
+        ] {
+            let error = mongodb::error::Error::from(kind);
+            assert_eq!(
+                classify_insert_error(&error),
+                InsertErrorKind::Transient,
+                "{error}"
+            );
+        }
+    }
+
+    #[test]
+    fn split_documents_reassemble_into_the_original() {
+        let accounts: Vec<Bson> = (0..100)
+            .map(|i| doc! {"derivation_path": format!("m/44'/501'/{i}'/0'")}.into())
+            .collect();
+        let document = doc! {"public_key": "AbCpump", "accounts": accounts.clone()};
+
+        let whole = split_document(document.clone(), MAX_BSON_SIZE).unwrap();
+        assert_eq!(whole.len(), 1);
+        assert_eq!(whole[0], document);
+        assert_eq!(reassemble(whole), document);
+
+        let mut parts = split_document(document.clone(), 1024).unwrap();
+        assert!(parts.len() > 2);
+        for (i, part) in parts.iter().enumerate() {
+            assert!(bson_len(part).unwrap() <= 1024);
+            assert_eq!(part.get_i32(PART_FIELD).unwrap(), i as i32);
+        }
+        // Readers fetch the later parts in whatever order the server returns them
+        parts.reverse();
+        assert_eq!(reassemble(parts), document);
     }
 }
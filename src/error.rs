@@ -0,0 +1,96 @@
+use crate::matcher::Base58Alphabet;
+
+/// Errors returned by the library. The binary turns them into `anyhow` errors.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// A pattern uses a character that never appears in addresses, so it could never match
+    #[error("Pattern '{pattern}' contains '{character}', which never appears in {alphabet:?} base58 addresses")]
+    InvalidPattern {
+        pattern: String,
+        character: char,
+        alphabet: Base58Alphabet,
+    },
+
+    /// An empty pattern matches every address
+    #[error("Empty pattern given, which would match every address; check the shell quoting")]
+    EmptyPattern,
+
+    /// A pattern contains whitespace, usually from a shell-quoting mistake
+    #[error("Pattern '{pattern}' contains whitespace; check the shell quoting")]
+    WhitespaceInPattern { pattern: String },
+
+    /// A config file couldn't be read or holds invalid settings
+    #[error("{0}")]
+    Config(String),
+
+    /// A sink's server couldn't be reached or refused the connection
+    #[error("{0}")]
+    Connect(String),
+
+    /// A MongoDB operation failed
+    #[cfg(feature = "mongodb")]
+    #[error(transparent)]
+    Db(#[from] mongodb::error::Error),
+
+    /// A wallet couldn't be encoded as a MongoDB document
+    #[cfg(feature = "mongodb")]
+    #[error(transparent)]
+    Bson(#[from] mongodb::bson::ser::Error),
+
+    /// Writing a CSV export failed
+    #[cfg(feature = "mongodb")]
+    #[error(transparent)]
+    Csv(#[from] csv::Error),
+
+    /// A SQLite operation failed
+    #[cfg(feature = "sqlcipher")]
+    #[error(transparent)]
+    Sqlite(#[from] rusqlite::Error),
+
+    /// The encrypted database couldn't be unlocked, or encryption isn't available
+    #[error("{0}")]
+    Encryption(String),
+
+    /// A save didn't finish within the configured timeout
+    #[error("save timed out after {0} seconds")]
+    Timeout(u64),
+
+    /// A save failure that retrying won't fix, such as a rejected document or an
+    /// exhausted storage quota. Sinks return it so the writer spills the wallet straight away.
+    #[error("{reason}: {source}")]
+    PermanentSave {
+        reason: &'static str,
+        source: Box<Error>,
+    },
+
+    /// One or more sinks of a [`MultiSink`](crate::sink::MultiSink) failed
+    #[error("{} sink(s) failed: {}", .0.len(), join_errors(.0))]
+    Sinks(Vec<Error>),
+
+    /// The `--on-found` command couldn't be started
+    #[error("Failed to start --on-found command {program}: {source}")]
+    Hook {
+        program: String,
+        source: std::io::Error,
+    },
+
+    /// The global logger was already set
+    #[error(transparent)]
+    Logging(#[from] log::SetLoggerError),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+fn join_errors(errors: &[Error]) -> String {
+    errors
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("; ")
+}
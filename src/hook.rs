@@ -0,0 +1,55 @@
+use crate::error::{Error, Result};
+use crate::sink::WalletDocument;
+use log::{error, info, warn};
+use std::path::Path;
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tokio::task::JoinHandle;
+use zeroize::Zeroizing;
+
+/// Start the `--on-found` command for a found wallet. The public key is passed as the
+/// only argument and, if `pass_private_key` is set, the private key is written to
+/// the command's stdin. The returned task waits for the command and logs how it exited.
+pub fn spawn_on_found(
+    program: &Path,
+    wallet: &WalletDocument,
+    pass_private_key: bool,
+) -> Result<JoinHandle<()>> {
+    let mut child = Command::new(program)
+        .arg(&wallet.public_key)
+        .stdin(if pass_private_key {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .spawn()
+        .map_err(|source| Error::Hook {
+            program: program.display().to_string(),
+            source,
+        })?;
+
+    let private_key = pass_private_key.then(|| Zeroizing::new(format!("{}\n", wallet.private_key)));
+    let program = program.display().to_string();
+    let public_key = wallet.public_key.clone();
+
+    Ok(tokio::spawn(async move {
+        if let (Some(mut stdin), Some(private_key)) = (child.stdin.take(), private_key) {
+            // Dropping stdin afterwards closes it, so the command sees end of input
+            if let Err(e) = stdin.write_all(private_key.as_bytes()).await {
+                warn!("Failed to pass the private key to {}: {}", program, e);
+            }
+        }
+
+        match child.wait().await {
+            Ok(status) if status.success() => {
+                info!("--on-found command finished for {}", public_key)
+            }
+            Ok(status) => error!(
+                "--on-found command {} failed for {} with {}",
+                program, public_key, status
+            ),
+            Err(e) => error!("--on-found command {} could not be awaited: {}", program, e),
+        }
+    }))
+}
@@ -0,0 +1,169 @@
+pub mod binary;
+pub mod config;
+#[cfg(feature = "dashboard")]
+pub mod dashboard;
+#[cfg(feature = "mongodb")]
+pub mod db;
+pub mod error;
+pub mod hook;
+pub mod logging;
+pub mod matcher;
+pub mod nats;
+pub mod selftest;
+pub mod sink;
+#[cfg(feature = "sqlcipher")]
+pub mod sqlite;
+pub mod stats;
+#[cfg(feature = "tui")]
+pub mod tui;
+pub mod wallet_generator;
+pub mod writer;
+
+pub use crate::error::{Error, Result};
+
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use futures::Stream;
+use solana_sdk::signature::Keypair;
+use tokio::sync::mpsc;
+
+use crate::matcher::MatchConfig;
+use crate::wallet_generator::{FoundWallet, GeneratedWallet, WalletGenerator};
+
+/// Search for a single vanity wallet on `threads` OS threads, blocking until one is found.
+/// Returns the wallet with the number of attempts all threads made up to it.
+///
+/// The first thread to find a match claims it and every other thread stops
+/// immediately, so exactly one keypair is returned even if several threads
+/// hit a match at the same moment.
+pub fn find_vanity_wallet(
+    wallet_generator: &WalletGenerator,
+    threads: usize,
+) -> (GeneratedWallet, u64) {
+    let done = AtomicBool::new(false);
+    let winner = Mutex::new(None);
+    let attempts = AtomicU64::new(0);
+
+    std::thread::scope(|scope| {
+        for _ in 0..threads.max(1) {
+            scope.spawn(|| {
+                let mut wallet_generator = wallet_generator.clone();
+                // Counted locally so the hot loop never touches a shared cache line
+                let mut tried = 0;
+                while !done.load(Ordering::Relaxed) {
+                    tried += 1;
+                    if let Some(FoundWallet { wallet, .. }) = wallet_generator.try_one() {
+                        // The exchange only has to be atomic: the winner is handed over
+                        // through the mutex, and the scope's join publishes it
+                        if done
+                            .compare_exchange(false, true, Ordering::Relaxed, Ordering::Relaxed)
+                            .is_ok()
+                        {
+                            *winner.lock().unwrap() = Some(wallet);
+                        }
+                    }
+                }
+                attempts.fetch_add(tried, Ordering::Relaxed);
+            });
+        }
+    });
+
+    let wallet = winner
+        .into_inner()
+        .unwrap()
+        .expect("the search only stops once a thread has claimed a match");
+    (wallet, attempts.into_inner())
+}
+
+/// Search on `threads` OS threads and yield matching keypairs as they're found.
+///
+/// The workers run until the stream is dropped, then stop on their next attempt.
+/// Only a few matches are buffered, so a slow consumer pauses the search.
+///
+/// ```
+/// use futures::StreamExt;
+/// use solana_sdk::signature::Signer;
+/// use solana_vanity_wallet::matcher::MatchConfig;
+///
+/// # tokio::runtime::Runtime::new().unwrap().block_on(async {
+/// let stream = solana_vanity_wallet::vanity_stream(MatchConfig::new(&["a".to_string()]), 2);
+/// let found: Vec<_> = stream.take(1).collect().await;
+/// assert!(found[0].pubkey().to_string().ends_with('a'));
+/// # });
+/// ```
+pub fn vanity_stream(config: MatchConfig, threads: usize) -> impl Stream<Item = Keypair> {
+    let threads = threads.max(1);
+    let (tx, mut rx) = mpsc::channel(threads);
+    let wallet_generator = WalletGenerator::new(config);
+
+    for _ in 0..threads {
+        let tx = tx.clone();
+        let mut wallet_generator = wallet_generator.clone();
+        std::thread::spawn(move || {
+            // The channel closes when the stream is dropped
+            while !tx.is_closed() {
+                if let Some(FoundWallet { wallet, .. }) = wallet_generator.try_one() {
+                    if tx.blocking_send(wallet.keypair).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+    }
+
+    futures::stream::poll_fn(move |cx| rx.poll_recv(cx))
+}
+
+/// Run the full generate-and-match loop on `threads` OS threads for `duration`
+/// and return the combined wallets per second
+pub fn measure_throughput(
+    wallet_generator: &WalletGenerator,
+    threads: usize,
+    duration: Duration,
+) -> f64 {
+    let attempts = AtomicUsize::new(0);
+    let started = Instant::now();
+
+    std::thread::scope(|scope| {
+        for _ in 0..threads.max(1) {
+            scope.spawn(|| {
+                let mut wallet_generator = wallet_generator.clone();
+                let mut local_attempts = 0;
+                while started.elapsed() < duration {
+                    wallet_generator.try_one();
+                    local_attempts += 1;
+                }
+                attempts.fetch_add(local_attempts, Ordering::Relaxed);
+            });
+        }
+    });
+
+    attempts.into_inner() as f64 / started.elapsed().as_secs_f64()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wallet_generator::SequenceKeypairs;
+    use solana_sdk::signature::Signer;
+
+    #[test]
+    fn find_vanity_wallet_counts_the_attempts_up_to_the_find() {
+        let hit = Keypair::new();
+        let address = hit.pubkey().to_string();
+        let suffix = address[address.len() - 4..].to_string();
+        let miss = || {
+            std::iter::repeat_with(Keypair::new)
+                .find(|keypair| !keypair.pubkey().to_string().ends_with(&suffix))
+                .unwrap()
+        };
+
+        let generator = WalletGenerator::new(MatchConfig::new(std::slice::from_ref(&suffix)))
+            .with_keypair_source(Box::new(SequenceKeypairs::new(vec![miss(), miss(), hit])));
+        let (wallet, attempts) = find_vanity_wallet(&generator, 1);
+        assert_eq!(wallet.pubkey, address);
+        assert_eq!(attempts, 3);
+    }
+}
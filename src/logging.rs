@@ -0,0 +1,135 @@
+use crate::error::Result;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Number of rotated log files kept next to the active one (`<path>.1` .. `<path>.N`)
+const MAX_ROTATED_FILES: usize = 5;
+
+/// A log file that is rotated once it grows past a size limit
+pub struct RotatingFile {
+    path: PathBuf,
+    max_size: u64,
+    file: File,
+    size: u64,
+}
+
+impl RotatingFile {
+    pub fn new(path: &Path, max_size: u64) -> Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            max_size,
+            file,
+            size,
+        })
+    }
+
+    fn rotated_path(&self, index: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", index));
+        path.into()
+    }
+
+    /// Shift `<path>.N-1` to `<path>.N`, ..., `<path>` to `<path>.1` and reopen
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        for index in (1..MAX_ROTATED_FILES).rev() {
+            let from = self.rotated_path(index);
+            if from.exists() {
+                fs::rename(&from, self.rotated_path(index + 1))?;
+            }
+        }
+        fs::rename(&self.path, self.rotated_path(1))?;
+
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.size > 0 && self.size + buf.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Set while a full-screen UI owns the terminal, so log lines don't draw over it
+static CONSOLE_SUSPENDED: AtomicBool = AtomicBool::new(false);
+
+/// Stop or resume writing log records to the console; the log file, if any, still
+/// gets every record. Only takes effect if the logger was set up to be suspendable.
+pub fn suspend_console(suspended: bool) {
+    CONSOLE_SUSPENDED.store(suspended, Ordering::Relaxed);
+}
+
+/// Sends every log record to the console (unless quiet) and an optional log file
+struct LogWriter {
+    console: bool,
+    file: Option<RotatingFile>,
+}
+
+impl Write for LogWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.console && !CONSOLE_SUSPENDED.load(Ordering::Relaxed) {
+            io::stderr().write_all(buf)?;
+        }
+        if let Some(file) = &mut self.file {
+            file.write_all(buf)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.console {
+            io::stderr().flush()?;
+        }
+        if let Some(file) = &mut self.file {
+            file.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// Initialize the global logger, honoring `RUST_LOG` for the level filter. With
+/// `suspendable`, console output can be switched off later with [`suspend_console`].
+pub fn init_logging(
+    log_file: Option<&Path>,
+    max_size_mb: u64,
+    quiet: bool,
+    suspendable: bool,
+) -> Result<()> {
+    // Default to `info` so progress is visible without setting RUST_LOG
+    let mut builder =
+        env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info"));
+
+    // Plain env_logger output keeps colors on the terminal when no file is involved
+    if log_file.is_some() || quiet || suspendable {
+        let file = log_file
+            .map(|path| RotatingFile::new(path, max_size_mb.max(1) * 1024 * 1024))
+            .transpose()?;
+        builder.target(env_logger::Target::Pipe(Box::new(LogWriter {
+            console: !quiet,
+            file,
+        })));
+    }
+
+    builder.try_init()?;
+    Ok(())
+}
@@ -1,219 +1,2541 @@
-mod config;
-mod db;
-mod wallet_generator;
-
 use anyhow::Result;
-use clap::Parser;
+use clap::parser::ValueSource;
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
 use log::{error, info, warn};
-use solana_sdk::signature::Signer;
+use owo_colors::OwoColorize;
+use qrcode::{render::unicode, QrCode};
+use std::collections::{BTreeMap, HashSet};
+use std::io::{IsTerminal, Write};
+use std::path::PathBuf;
 use std::sync::{
-    atomic::{AtomicUsize, Ordering},
+    atomic::{AtomicBool, AtomicUsize, Ordering},
     Arc,
 };
-use tokio::sync::Mutex;
+use std::time::Instant;
+
+use solana_sdk::pubkey::Pubkey;
+use solana_vanity_wallet::binary::{BinaryReader, BinarySink};
+use solana_vanity_wallet::config::{self, Config};
+#[cfg(feature = "dashboard")]
+use solana_vanity_wallet::dashboard;
+#[cfg(feature = "mongodb")]
+use solana_vanity_wallet::db::{ExportFormat, MongoDBClient, WriteConcernLevel};
+use solana_vanity_wallet::logging;
+use solana_vanity_wallet::matcher::{
+    self, Base58Alphabet, BeforeSuffix, FuzzyMatch, MatchConfig, MatchMode, MatchTarget,
+    PatternMatch,
+};
+use solana_vanity_wallet::nats::NatsSink;
+use solana_vanity_wallet::selftest;
+use solana_vanity_wallet::sink::{
+    read_wallet_file, FileSink, InMemorySink, MultiSink, SeedAccount, WalletDocument, WalletSink,
+};
+#[cfg(feature = "sqlcipher")]
+use solana_vanity_wallet::sqlite::SqliteSink;
+use solana_vanity_wallet::stats::{self, RarestFind, RateEma, RunSummary, StopReason, Throttle};
+#[cfg(any(feature = "dashboard", feature = "tui"))]
+use solana_vanity_wallet::stats::{LiveStats, RecentFind, RECENT_FINDS};
+#[cfg(feature = "tui")]
+use solana_vanity_wallet::tui;
+use solana_vanity_wallet::wallet_generator::{FoundWallet, GeneratedWallet, WalletGenerator};
+use solana_vanity_wallet::writer::{
+    save_with_retries, wallet_queue, FullPolicy, WalletSender, WalletWriter, WRITER_QUEUE_SIZE,
+};
+use solana_vanity_wallet::{find_vanity_wallet, measure_throughput};
+
+const EXAMPLES: &str = "\
+Examples:
+  Search for \"pump\" on every core and store matches in MongoDB:
+    solana-vanity-wallet
+
+  Search for pump.fun-style addresses with the recommended settings:
+    solana-vanity-wallet --pumpfun -o wallets.jsonl
 
-use crate::config::Config;
-use crate::db::MongoDBClient;
-use crate::wallet_generator::WalletGenerator;
+  Search for two suffixes on 8 threads, writing matches to a file:
+    solana-vanity-wallet -t 8 -s pump,moon -o wallets.jsonl
+
+  Find a single recoverable wallet and show it as a QR code:
+    solana-vanity-wallet -s abc --single --mnemonic --qr
+
+  Check the setup and estimate the time to the first match:
+    solana-vanity-wallet -s pump doctor";
 
 #[derive(Parser)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, after_help = EXAMPLES)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Load settings from this TOML file; flags and environment variables take precedence
+    #[arg(long)]
+    config: Option<PathBuf>,
+
     /// Number of threads to use for wallet generation
     #[arg(short, long, env = "THREADS", default_value_t = num_cpus::get())]
     threads: usize,
 
-    /// MongoDB connection string
+    /// Benchmark 1, half and all of --threads at startup and run with the fastest
+    #[arg(long)]
+    auto_threads: bool,
+
+    /// MongoDB connection string [default: mongodb://localhost:27017]; given alongside
+    /// another sink, wallets are stored in both
+    #[cfg(feature = "mongodb")]
     #[arg(short, long, env = "MONGODB_URI")]
     mongodb_uri: Option<String>,
 
-    /// MongoDB database name
-    #[arg(long, default_value = "vanity_wallets")]
-    db_name: String,
+    /// MongoDB database name
+    #[cfg(feature = "mongodb")]
+    #[arg(long, default_value = "vanity_wallets")]
+    db_name: String,
+
+    /// MongoDB collection name
+    #[cfg(feature = "mongodb")]
+    #[arg(long, default_value = "wallets")]
+    collection_name: String,
+
+    /// Append the run's start time to the collection name (e.g. wallets_20240601_120000)
+    /// so each run writes to its own collection
+    #[cfg(feature = "mongodb")]
+    #[arg(long)]
+    collection_suffix_timestamp: bool,
+
+    /// MongoDB write concern required for each save
+    #[cfg(feature = "mongodb")]
+    #[arg(long, value_enum, default_value_t = WriteConcernLevel::One)]
+    write_concern: WriteConcernLevel,
+
+    /// Delete stored wallets from MongoDB this many seconds after they were found, for
+    /// throwaway experiment collections. Needs --yes, as expired keys are gone for good.
+    #[cfg(feature = "mongodb")]
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    ttl_seconds: Option<u64>,
+
+    /// Confirm a setting that deletes data, such as --ttl-seconds
+    #[cfg(feature = "mongodb")]
+    #[arg(long)]
+    yes: bool,
+
+    /// Append found wallets to this file as JSON lines (replaces MongoDB unless --mongodb-uri is given)
+    #[arg(short, long)]
+    output_file: Option<PathBuf>,
+
+    /// Gzip-compress --output-file, appending ".gz" to its name if it doesn't end with it
+    #[arg(long, requires = "output_file")]
+    compress: bool,
+
+    /// Append found wallets to this file as compact fixed-size binary records, to be
+    /// loaded into a database later with `import` (replaces MongoDB unless --mongodb-uri is given)
+    #[arg(long, conflicts_with_all = ["mnemonic", "match_ata"])]
+    binary_output: Option<PathBuf>,
+
+    /// Publish a JSON event per found wallet to this NATS server (replaces MongoDB unless
+    /// --mongodb-uri is given)
+    #[arg(long)]
+    nats_url: Option<String>,
+
+    /// Store found wallets in this SQLCipher database, encrypted with --sqlite-key
+    /// (replaces MongoDB unless --mongodb-uri is given; needs the sqlcipher feature)
+    #[arg(long)]
+    sqlite_file: Option<PathBuf>,
+
+    /// Passphrase the --sqlite-file database is encrypted with
+    #[arg(long, env = "SQLITE_KEY", hide_env_values = true)]
+    sqlite_key: Option<String>,
+
+    /// NATS subject found-wallet events are published to
+    #[arg(long, default_value = "vanity.wallets")]
+    nats_subject: String,
+
+    /// Include the private key in published NATS events
+    #[arg(long)]
+    nats_include_private_key: bool,
+
+    /// The suffix to search for in wallet addresses; repeat or comma-separate to search for
+    /// several [default: pump, unless --fuzzy is given]
+    #[arg(short, long, value_delimiter = ',')]
+    suffix: Vec<String>,
+
+    /// Search for the longest ending of this target instead of a fixed suffix: every
+    /// find that matches more of the target than the best so far is saved, and the run
+    /// stops once an address ends with all of it
+    #[arg(long, value_name = "TARGET", conflicts_with_all = ["suffix", "fuzzy", "prefix", "single"])]
+    progressive: Option<String>,
+
+    /// Match addresses whose start or end is within --max-distance edits of this string
+    /// (replaces the exact suffix check)
+    #[arg(long)]
+    fuzzy: Option<String>,
+
+    /// Maximum edit distance allowed by --fuzzy
+    #[arg(long, default_value_t = 1, requires = "fuzzy")]
+    max_distance: usize,
+
+    /// Reject --fuzzy matches covering fewer than this many address characters
+    /// (the target length minus the edits)
+    #[arg(long, requires = "fuzzy")]
+    min_match_len: Option<usize>,
+
+    /// Also require the --before-suffix-len characters right before the suffix to be
+    /// among these (e.g. "123456789" for digits before "pump")
+    #[arg(long, conflicts_with = "fuzzy")]
+    before_suffix_chars: Option<String>,
+
+    /// Also require addresses to start with this exact (case-sensitive) prefix
+    #[arg(long, conflicts_with = "fuzzy")]
+    prefix: Option<String>,
+
+    /// Match a prefix or suffix without uppercase letters in any case; patterns
+    /// with uppercase letters still match exactly
+    #[arg(long, conflicts_with = "fuzzy")]
+    smart_case: bool,
+
+    /// With --prefix, count an address matching either the prefix or a suffix,
+    /// instead of requiring both
+    #[arg(long, requires = "prefix")]
+    any_of: bool,
+
+    /// Number of characters before the suffix checked by --before-suffix-chars
+    #[arg(long, default_value_t = 1, requires = "before_suffix_chars")]
+    before_suffix_len: usize,
+
+    /// Base58 alphabet used to validate the pattern
+    #[arg(long, value_enum, default_value_t = Base58Alphabet::Bitcoin)]
+    alphabet: Base58Alphabet,
+
+    /// Reject addresses containing any of these characters (e.g. "0OIl")
+    #[arg(long)]
+    forbidden_chars: Option<String>,
+
+    /// Only accept addresses made up entirely of these characters
+    #[arg(long)]
+    required_chars: Option<String>,
+
+    /// Only accept addresses at least this many characters long
+    #[arg(long)]
+    min_address_len: Option<usize>,
+
+    /// Only accept addresses at most this many characters long
+    #[arg(long)]
+    max_address_len: Option<usize>,
+
+    /// Reject addresses with a run of the same character longer than this
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    max_repeat: Option<u64>,
+
+    /// Only accept addresses with at least this many different characters
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..=58))]
+    min_distinct: Option<u64>,
+
+    /// Only accept addresses whose characters have at least this much Shannon entropy,
+    /// in bits per character (random addresses average about 4.8)
+    #[arg(long, value_name = "BITS")]
+    min_entropy: Option<f64>,
+
+    /// Reject addresses containing this substring in any case, e.g. to avoid profanity;
+    /// repeat or comma-separate to exclude several
+    #[arg(long, value_name = "SUBSTR", value_delimiter = ',')]
+    exclude: Vec<String>,
+
+    /// Log (at debug level) addresses that end with a suffix missing at most N of its
+    /// leading characters, to get a feel for how close the search is getting
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..), conflicts_with = "fuzzy")]
+    log_near_misses: Option<u64>,
+
+    /// Seconds to wait for a single save attempt before treating it as failed
+    #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u64).range(1..))]
+    save_timeout_secs: u64,
+
+    /// Found wallets queued for the writer before --writer-full-policy applies
+    #[arg(long, default_value_t = WRITER_QUEUE_SIZE as u64, value_parser = clap::value_parser!(u64).range(1..))]
+    writer_queue_size: u64,
+
+    /// What to do with a find when the writer queue is full: block slows generation
+    /// to the sink's pace, drop-oldest and drop-newest lose wallets to keep generating
+    #[arg(long, value_enum, default_value_t = FullPolicy::Block)]
+    writer_full_policy: FullPolicy,
+
+    /// Stop after the first match: all threads are cancelled and exactly one wallet is saved
+    #[arg(long)]
+    single: bool,
+
+    /// Stop gracefully after this long regardless of matches, e.g. "30m" or "2h"
+    #[arg(long, value_parser = humantime::parse_duration, conflicts_with = "single")]
+    max_runtime: Option<std::time::Duration>,
+
+    /// Stop gracefully once this many wallets were found
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..), conflicts_with = "single")]
+    count: Option<u64>,
+
+    /// Restarts a worker thread gets after consecutive failures before it gives up;
+    /// the run exits with an error once every thread has given up
+    #[arg(long, default_value_t = 5)]
+    max_consecutive_failures: u32,
+
+    /// Milliseconds a failed worker waits before restarting (0 restarts at once); the
+    /// wait doubles with each further failure in a row, up to 30 seconds
+    #[arg(long, default_value_t = 1000)]
+    restart_delay_ms: u64,
+
+    /// Smoothing factor for the displayed generation rate (0 < alpha <= 1, higher reacts faster)
+    #[arg(long, default_value_t = 0.3)]
+    rate_ema_alpha: f64,
+
+    /// Log progress every this many seconds instead of every 100,000 wallets, so
+    /// the log rate doesn't depend on how fast the machine is
+    #[arg(long, value_name = "SECS", value_parser = clap::value_parser!(u64).range(1..))]
+    progress_every_secs: Option<u64>,
+
+    /// Also write logs to this file, rotating it once it reaches --log-max-size-mb
+    #[arg(long)]
+    log_file: Option<PathBuf>,
+
+    /// Size in megabytes at which the log file is rotated
+    #[arg(long, default_value_t = 100)]
+    log_max_size_mb: u64,
+
+    /// Disable console logging (logs still go to --log-file if set)
+    #[arg(short, long)]
+    quiet: bool,
+
+    /// Don't show the colored banner for found wallets
+    #[arg(long)]
+    no_color: bool,
+
+    /// Pin each runtime thread to its own CPU core for better cache locality
+    #[arg(long)]
+    pin_threads: bool,
+
+    /// Write wallets that fail every save retry to this JSON-lines file so they're never lost
+    #[arg(long)]
+    spill_on_db_failure: Option<PathBuf>,
+
+    /// Replay the spill file into the sink at startup and whenever a save succeeds again
+    #[arg(long, requires = "spill_on_db_failure")]
+    replay_spill: bool,
+
+    /// Derive wallets from random BIP39 mnemonics so each match is recoverable from a seed phrase
+    #[arg(long)]
+    mnemonic: bool,
+
+    /// Number of BIP44 account indices (m/44'/501'/N'/0') scanned per mnemonic
+    #[arg(long, default_value_t = 1, requires = "mnemonic", value_parser = clap::value_parser!(u32).range(1..))]
+    derivation_scan: u32,
+
+    /// Save each matching mnemonic once, as one document listing all of its scanned
+    /// accounts, instead of one document per matching account
+    #[arg(long, requires = "mnemonic")]
+    group_by_seed: bool,
+
+    /// Print a terminal QR code of each found wallet's public key
+    #[arg(long)]
+    qr: bool,
+
+    /// Yield to the async scheduler after this many attempts per worker
+    #[arg(long, default_value_t = 1000, value_parser = clap::value_parser!(u64).range(1..))]
+    yield_every: u64,
+
+    /// Match the pattern against each keypair's associated token account for this
+    /// mint instead of its own public key
+    #[arg(long, value_name = "MINT")]
+    match_ata: Option<String>,
+
+    /// Match the pattern against each keypair's base58 private key instead of its
+    /// address. Purely cosmetic: the private key never appears on-chain
+    #[arg(long, conflicts_with = "match_ata")]
+    match_private: bool,
+
+    /// Only accept addresses on the ed25519 curve, i.e. usable as signing wallets
+    #[arg(long, default_value_t = true, action = clap::ArgAction::Set)]
+    require_on_curve: bool,
+
+    /// Cap the combined generation rate at this many wallets per second
+    #[arg(long)]
+    max_rate: Option<f64>,
+
+    /// Serve a live stats dashboard on this port (all interfaces)
+    #[cfg(feature = "dashboard")]
+    #[arg(long)]
+    dashboard_port: Option<u16>,
+
+    /// Show a full-screen terminal dashboard instead of log lines: p pauses and
+    /// resumes, q quits gracefully. Logs still go to --log-file if set.
+    #[cfg(feature = "tui")]
+    #[arg(long, conflicts_with_all = ["emit_stdout", "qr", "single"])]
+    tui: bool,
+
+    /// JSON file the run summary is written to on exit
+    #[arg(long, default_value = "run-summary.json")]
+    summary_file: PathBuf,
+
+    /// Keep a JSON object of finds per pattern in this file, rewritten every
+    /// --count-by-pattern-interval-secs seconds and on exit
+    #[arg(long, value_name = "PATH")]
+    count_by_pattern_file: Option<PathBuf>,
+
+    /// Seconds between rewrites of --count-by-pattern-file
+    #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u64).range(1..))]
+    count_by_pattern_interval_secs: u64,
+
+    /// Print "pubkey<TAB>private_key" to stdout for each match; logs stay on stderr
+    #[arg(long)]
+    emit_stdout: bool,
+
+    /// Print the expected hit rate of each pattern and exit without generating
+    #[arg(long)]
+    explain: bool,
+
+    /// Measure throughput for a few seconds, print the expected attempts and the
+    /// median, 90th and 99th percentile time to the first match, and exit
+    #[arg(long)]
+    estimate_only: bool,
+
+    /// Keep found wallets in memory instead of saving them, to measure throughput
+    /// including the save path without a database. Found wallets are lost on exit!
+    #[arg(long)]
+    no_persist_benchmark: bool,
+
+    /// Log what finding a wallet ending with SUFFIX looks like, with clearly fake
+    /// keys, and exit without generating anything; for previewing or testing log parsers
+    #[arg(long, value_name = "SUFFIX")]
+    simulate: Option<String>,
+
+    /// Run this program for each found wallet, with the public key as its argument
+    #[arg(long)]
+    on_found: Option<PathBuf>,
+
+    /// Also pass the private key to the --on-found program on its stdin
+    #[arg(long, requires = "on_found")]
+    on_found_private_key: bool,
+
+    /// With --emit-stdout, print only the public key
+    #[arg(long, requires = "emit_stdout")]
+    pubkey_only: bool,
+
+    /// Don't log private keys or mnemonics; they are still saved to the sink
+    #[arg(long)]
+    no_log_secrets: bool,
+
+    /// Fail instead of searching for the default "pump" suffix when no pattern is given
+    #[arg(long)]
+    require_explicit_pattern: bool,
+
+    /// pump.fun preset: case-sensitive suffix "pump", --spill-on-db-failure
+    /// pumpfun-spill.jsonl and --no-log-secrets; flags given alongside it win
+    #[arg(long, conflicts_with_all = ["fuzzy", "progressive"])]
+    pumpfun: bool,
+}
+
+impl Cli {
+    /// Whether the full-screen TUI was asked for; never without the `tui` feature
+    fn wants_tui(&self) -> bool {
+        #[cfg(feature = "tui")]
+        return self.tui;
+        #[cfg(not(feature = "tui"))]
+        false
+    }
+}
+
+/// Suffix searched for when no pattern is given
+const DEFAULT_SUFFIX: &str = "pump";
+
+/// Spill file used by --pumpfun unless --spill-on-db-failure is given
+const PUMPFUN_SPILL_FILE: &str = "pumpfun-spill.jsonl";
+
+/// Apply the --pumpfun preset to every setting that wasn't given explicitly
+fn apply_pumpfun_preset(config: &mut Config, explicit_args: &HashSet<String>) {
+    if !explicit_args.contains("suffix") {
+        config.suffix = vec!["pump".to_string()];
+    }
+    if !explicit_args.contains("smart_case") {
+        config.smart_case = false;
+    }
+    if !explicit_args.contains("spill_on_db_failure") {
+        config.spill_on_db_failure = Some(PathBuf::from(PUMPFUN_SPILL_FILE));
+    }
+    if !explicit_args.contains("no_log_secrets") {
+        config.no_log_secrets = true;
+    }
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Check the pattern, sink connection and throughput, and estimate the time to a match
+    Doctor {
+        /// Seconds spent measuring generation throughput
+        #[arg(long, default_value_t = 5)]
+        sample_secs: u64,
+    },
+    /// Check that keys, base58 encoding and the matcher behave on this platform,
+    /// without touching any sink
+    Selftest,
+    /// Export all wallets stored in MongoDB to a file
+    #[cfg(feature = "mongodb")]
+    Export {
+        /// Output file format
+        #[arg(long, value_enum, default_value_t = ExportFormat::Csv)]
+        format: ExportFormat,
+
+        /// Path of the file to write
+        #[arg(long)]
+        out: PathBuf,
+    },
+    /// Save the wallets in a --binary-output file to the configured sinks
+    Import {
+        /// Binary file to read
+        #[arg(long)]
+        file: PathBuf,
+    },
+    /// Save the wallets in a --spill-on-db-failure file to the configured sinks,
+    /// skipping those that are already stored
+    Resume {
+        /// Spill file to read
+        #[arg(long)]
+        file: PathBuf,
+    },
+    /// List stored wallets that were found with a pattern
+    #[cfg(feature = "mongodb")]
+    List {
+        /// Pattern the wallets were found with
+        #[arg(long)]
+        pattern: String,
+
+        /// Maximum number of wallets to show, newest first
+        #[arg(long, default_value_t = 20)]
+        limit: i64,
+    },
+    /// Check that every stored private key re-derives its public key
+    #[cfg(feature = "mongodb")]
+    Verify,
+    /// Report how many stored wallets are at each schema version. No migrations exist
+    /// yet, so nothing is changed.
+    #[cfg(feature = "mongodb")]
+    Migrate,
+    /// Delete all stored wallets that were found with a pattern
+    #[cfg(feature = "mongodb")]
+    Delete {
+        /// Pattern the wallets were found with
+        #[arg(long)]
+        pattern: String,
+
+        /// Actually delete; without this only the number of matching wallets is printed
+        #[arg(long)]
+        yes: bool,
+    },
+}
+
+/// Render a public key as a QR code for the terminal
+fn render_qr_code(pubkey: &str) -> Result<String> {
+    let code = QrCode::new(pubkey.as_bytes())?;
+    Ok(code
+        .render::<unicode::Dense1x2>()
+        .dark_color(unicode::Dense1x2::Light)
+        .light_color(unicode::Dense1x2::Dark)
+        .build())
+}
+
+/// Print a found wallet's QR code, on stderr when stdout carries --emit-stdout data
+fn show_qr_code(config: &Config, pubkey: &str) {
+    match render_qr_code(pubkey) {
+        Ok(code) if config.emit_stdout => eprintln!("{}", code),
+        Ok(code) => println!("{}", code),
+        Err(e) => warn!("Failed to render QR code: {}", e),
+    }
+}
+
+/// The text a found banner shows: the matched address, or with --match-private the
+/// private key, unless --no-log-secrets keeps it off the terminal
+fn banner_text<'a>(config: &Config, wallet: &'a GeneratedWallet) -> &'a str {
+    if config.no_log_secrets && wallet.private_key.is_some() {
+        &wallet.pubkey
+    } else {
+        wallet.matched_address()
+    }
+}
+
+/// Print a colored box highlighting a found wallet on the console, above its log lines
+fn print_found_banner(pubkey: &str, pattern: &str) {
+    let title = "VANITY WALLET FOUND";
+    let pattern = format!("Matched pattern: '{}'", pattern);
+    let width = pubkey.len().max(pattern.len()).max(title.len());
+    let border = "─".repeat(width + 2);
+    // Pad before coloring so the escape codes don't throw off the alignment
+    let row = |text: String| eprintln!("{} {} {}", "│".green(), text, "│".green());
+
+    eprintln!("{}", format!("┌{}┐", border).green());
+    row(format!("{:^width$}", title).green().bold().to_string());
+    row(format!("{:<width$}", pubkey).bold().to_string());
+    row(format!("{:<width$}", pattern).yellow().to_string());
+    eprintln!("{}", format!("└{}┘", border).green());
+}
+
+/// Write a found wallet to stdout for --emit-stdout, flushing so pipes see it at once
+fn emit_found(config: &Config, wallet: &GeneratedWallet) -> std::io::Result<()> {
+    let mut stdout = std::io::stdout().lock();
+    if config.pubkey_only {
+        writeln!(stdout, "{}", wallet.pubkey)?;
+    } else {
+        let private_key = WalletGenerator::get_private_key_string(&wallet.keypair);
+        writeln!(stdout, "{}\t{}", wallet.pubkey, private_key.as_str())?;
+    }
+    stdout.flush()
+}
+
+/// Attempts between progress updates, unless --progress-every-secs is set
+const PROGRESS_INTERVAL: usize = 100_000;
+
+/// Attempts each worker counts locally before adding them to the shared counter
+const COUNTER_BATCH: usize = 10_000;
+
+/// A worker that ran this long before failing starts counting its failures afresh
+const FAILURE_RESET_AFTER: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Longest a worker waits before a restart, however often it failed
+const MAX_RESTART_DELAY: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// How long a worker waits before restarting after its `failures`th failure in a row:
+/// `--restart-delay-ms`, doubled for each failure after the first
+fn restart_delay(base_ms: u64, failures: u32) -> std::time::Duration {
+    let factor = 1u64 << failures.saturating_sub(1).min(32);
+    std::time::Duration::from_millis(base_ms.saturating_mul(factor)).min(MAX_RESTART_DELAY)
+}
+
+/// A worker's failures in a row, deciding whether and when it restarts
+struct WorkerRestarts {
+    max_consecutive_failures: u32,
+    restart_delay_ms: u64,
+    consecutive_failures: u32,
+}
+
+impl WorkerRestarts {
+    fn new(config: &Config) -> Self {
+        Self {
+            max_consecutive_failures: config.max_consecutive_failures,
+            restart_delay_ms: config.restart_delay_ms,
+            consecutive_failures: 0,
+        }
+    }
+
+    /// Count a failure after a run of `ran_for`, returning how long to wait before
+    /// restarting, or `None` once the worker should give up
+    fn after_failure(&mut self, ran_for: std::time::Duration) -> Option<std::time::Duration> {
+        // A run that lasted a while means the last error was a one-off
+        if ran_for >= FAILURE_RESET_AFTER {
+            self.consecutive_failures = 0;
+        }
+        self.consecutive_failures += 1;
+        (self.consecutive_failures <= self.max_consecutive_failures)
+            .then(|| restart_delay(self.restart_delay_ms, self.consecutive_failures))
+    }
+}
+
+/// State shared between all worker threads.
+///
+/// The counters are statistics: nothing else is published through them and readers
+/// only need an eventually correct number, so they use `Relaxed`. `shutdown` is the
+/// one flag that steers control flow and pairs a `Release` store with `Acquire` loads.
+struct SharedState {
+    /// Attempts flushed by the workers; lags the true total by up to one batch per worker
+    counter: AtomicUsize,
+    /// The same flushed attempts split by worker, for the thread monitor
+    per_thread: Vec<AtomicUsize>,
+    found_wallets: AtomicUsize,
+    /// Finds per pattern, in the order the patterns were given
+    found_by_pattern: Vec<(String, AtomicUsize)>,
+    rate: std::sync::Mutex<RateEma>,
+    /// Set with --max-rate
+    throttle: Option<Throttle>,
+    /// When the search started, for the --count ETA
+    started: Instant,
+    /// Progress is logged by a ticker on a wall-clock cadence rather than by the workers
+    timed_progress: bool,
+    /// Set with --count; workers claim find slots up to it, so exactly this many are saved
+    count_target: Option<u64>,
+    /// Whether found wallets get a colored console banner
+    banner: bool,
+    /// Newest finds first, for the dashboard and the TUI
+    #[cfg(any(feature = "dashboard", feature = "tui"))]
+    recent_finds: std::sync::Mutex<std::collections::VecDeque<RecentFind>>,
+    progressive_best: ProgressiveBest,
+    /// Longest match so far; locked only when a wallet is found
+    rarest: std::sync::Mutex<Option<RarestFind>>,
+    shutdown: AtomicBool,
+    /// Toggled by SIGUSR1; paused workers sleep instead of generating
+    paused: AtomicBool,
+    /// Why a shutdown was requested; the first reason wins
+    stop_reason: std::sync::OnceLock<StopReason>,
+}
+
+/// Longest match of the `--progressive` target kept so far
+#[derive(Default)]
+struct ProgressiveBest(AtomicUsize);
+
+impl ProgressiveBest {
+    /// Whether a find beats the longest match so far; claiming it atomically means
+    /// only one of several equally long finds is kept
+    fn record_improvement(&self, len: usize) -> bool {
+        // The shared best is only read on matches, which are rare for all but the
+        // shortest endings, and only written when one improves on it
+        len > self.0.load(Ordering::Relaxed) && self.0.fetch_max(len, Ordering::Relaxed) < len
+    }
+}
+
+impl SharedState {
+    /// Ask every worker to stop, recording why unless a stop is already underway
+    fn request_stop(&self, reason: StopReason) {
+        let _ = self.stop_reason.set(reason);
+        self.shutdown.store(true, Ordering::Release);
+    }
+
+    /// Count a find, returning the new total, or `None` if --count finds were already
+    /// claimed by other workers and this one must not be saved
+    fn record_find(&self, pattern: &str) -> Option<usize> {
+        let total = stats::claim_find(&self.found_wallets, self.count_target)?;
+        // Stopping with the claim rather than after logging the find keeps other
+        // workers from generating on, which would inflate the summary's success rate
+        if self.count_target == Some(total as u64) {
+            self.request_stop(StopReason::Count);
+        }
+        if let Some((_, found)) = self.found_by_pattern.iter().find(|(p, _)| p == pattern) {
+            found.fetch_add(1, Ordering::Relaxed);
+        }
+        Some(total)
+    }
+
+    /// Keep a find if it beats the rarest one so far, returning whether it did
+    fn record_rarest(&self, pubkey: &str, found: &PatternMatch) -> bool {
+        let mut rarest = self.rarest.lock().unwrap();
+        if rarest
+            .as_ref()
+            .is_some_and(|rarest| rarest.matched_len >= found.len)
+        {
+            return false;
+        }
+        *rarest = Some(RarestFind {
+            public_key: pubkey.to_string(),
+            matched_pattern: found.pattern.to_string(),
+            matched_len: found.len,
+        });
+        true
+    }
+
+    /// Remember a find for the dashboard and the TUI, dropping the oldest beyond their limit
+    #[cfg(any(feature = "dashboard", feature = "tui"))]
+    fn record_recent(&self, pubkey: &str, pattern: &str) {
+        let mut recent = self.recent_finds.lock().unwrap();
+        recent.push_front(RecentFind {
+            public_key: pubkey.to_string(),
+            matched_pattern: pattern.to_string(),
+            found_at: chrono::Utc::now(),
+        });
+        recent.truncate(RECENT_FINDS);
+    }
+
+    /// Live statistics for the dashboard and the TUI
+    #[cfg(any(feature = "dashboard", feature = "tui"))]
+    fn live_stats(&self) -> LiveStats {
+        let elapsed_secs = self.started.elapsed().as_secs_f64();
+        let total_generated = self.counter.load(Ordering::Relaxed) as u64;
+        LiveStats {
+            elapsed_secs,
+            total_generated,
+            total_found: self.found_wallets.load(Ordering::Relaxed) as u64,
+            found_by_pattern: self.pattern_counts(),
+            wallets_per_second: self
+                .rate
+                .lock()
+                .unwrap()
+                .current()
+                .unwrap_or(total_generated as f64 / elapsed_secs.max(f64::EPSILON)),
+            recent_finds: self.recent_finds.lock().unwrap().iter().cloned().collect(),
+            paused: self.paused.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Finds so far, keyed by pattern
+    fn pattern_counts(&self) -> BTreeMap<String, u64> {
+        self.found_by_pattern
+            .iter()
+            .map(|(pattern, found)| (pattern.clone(), found.load(Ordering::Relaxed) as u64))
+            .collect()
+    }
+
+    /// Write the finds per pattern to --count-by-pattern-file, if set
+    fn write_pattern_counts(&self, config: &Config) {
+        let Some(path) = &config.count_by_pattern_file else {
+            return;
+        };
+        if let Err(e) = stats::write_pattern_counts(path, &self.pattern_counts()) {
+            warn!(
+                "Failed to write pattern counts to {}: {}",
+                path.display(),
+                e
+            );
+        }
+    }
+
+    /// Finds per pattern such as "pump: 420, moonx: 2", or `None` with a single pattern
+    fn pattern_breakdown(&self) -> Option<String> {
+        if self.found_by_pattern.len() < 2 {
+            return None;
+        }
+        let breakdown = self
+            .found_by_pattern
+            .iter()
+            .map(|(pattern, found)| format!("{}: {}", pattern, found.load(Ordering::Relaxed)))
+            .collect::<Vec<_>>();
+        Some(breakdown.join(", "))
+    }
+}
+
+#[cfg(feature = "tui")]
+impl tui::TuiControl for SharedState {
+    fn stats(&self) -> LiveStats {
+        self.live_stats()
+    }
+
+    fn toggle_pause(&self) {
+        self.paused.fetch_xor(true, Ordering::Relaxed);
+    }
+
+    fn quit(&self) {
+        self.request_stop(StopReason::Signal);
+    }
+
+    fn stopping(&self) -> bool {
+        self.shutdown.load(Ordering::Acquire)
+    }
+}
+
+/// Log generation statistics, either from the worker whose flush crossed a progress
+/// interval or, with --progress-every-secs, from the progress ticker
+fn print_progress(thread_id: Option<usize>, count: usize, state: &SharedState) {
+    let total_found = state.found_wallets.load(Ordering::Relaxed);
+    let wallets_per_second = state.rate.lock().unwrap().update(count);
+
+    info!("=== PROGRESS UPDATE ====");
+    if let Some(thread_id) = thread_id {
+        info!("Thread: {}", thread_id);
+    }
+    info!("Generated: {} wallets", count);
+    info!("Found: {} vanity wallets", total_found);
+    if let Some(breakdown) = state.pattern_breakdown() {
+        info!("Found by pattern: {}", breakdown);
+    }
+    if let Some(one_in) = stats::success_rate(count as u64, total_found as u64) {
+        info!("Success rate: 1 in {} wallets", one_in);
+    }
+    if let Some(target) = state.count_target {
+        let eta = stats::eta_secs(
+            total_found as u64,
+            target,
+            state.started.elapsed().as_secs_f64(),
+        )
+        .map_or_else(|| "estimating...".to_string(), stats::format_duration);
+        info!(
+            "Completion: {:.1}% of {} wallets, ETA {}",
+            total_found as f64 / target as f64 * 100.0,
+            target,
+            eta
+        );
+    }
+    info!(
+        "Performance: ~{:.2} wallets/second (~{:.2} million wallets/hour)",
+        wallets_per_second,
+        wallets_per_second * 3600.0 / 1_000_000.0
+    );
+    info!("=== CONTINUING SEARCH ====");
+}
+
+/// Add a worker's pending attempts to the shared counter, printing progress whenever
+/// the total crosses another interval. Returns the new total.
+fn flush_attempts(thread_id: usize, state: &SharedState, pending: &mut usize) -> usize {
+    state.per_thread[thread_id].fetch_add(*pending, Ordering::Relaxed);
+    let previous = state.counter.fetch_add(*pending, Ordering::Relaxed);
+    let count = previous + *pending;
+    *pending = 0;
+
+    if !state.timed_progress && count / PROGRESS_INTERVAL != previous / PROGRESS_INTERVAL {
+        print_progress(Some(thread_id), count, state);
+    }
+    count
+}
+
+/// Log a small table of each worker's total and its rate since the last call, so a
+/// thread that is stalled or starved by the scheduler stands out
+fn log_thread_rates(state: &SharedState, last_counts: &mut [usize], elapsed: std::time::Duration) {
+    info!("{:>6} {:>14} {:>12}", "Thread", "Generated", "Rate (/s)");
+    for (thread_id, (counter, last)) in state.per_thread.iter().zip(last_counts).enumerate() {
+        // Read once so the total and the rate agree
+        let count = counter.load(Ordering::Relaxed);
+        let rate = (count - *last) as f64 / elapsed.as_secs_f64();
+        *last = count;
+        info!("{:>6} {:>14} {:>12.0}", thread_id, count, rate);
+    }
+}
+
+/// Sleep for a throttle delay, waking up early if a shutdown is requested
+async fn throttle_sleep(delay: std::time::Duration, state: &SharedState) {
+    let deadline = tokio::time::Instant::now() + delay;
+    while !state.shutdown.load(Ordering::Acquire) && tokio::time::Instant::now() < deadline {
+        tokio::time::sleep_until(
+            deadline.min(tokio::time::Instant::now() + tokio::time::Duration::from_millis(250)),
+        )
+        .await;
+    }
+}
+
+/// How often a paused worker checks whether it may continue
+const PAUSE_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(250);
+
+/// Sleep while the run is paused, returning early if a shutdown is requested
+async fn wait_while_paused(state: &SharedState) {
+    while state.paused.load(Ordering::Relaxed) && !state.shutdown.load(Ordering::Acquire) {
+        tokio::time::sleep(PAUSE_POLL_INTERVAL).await;
+    }
+}
+
+/// Toggle pausing on every SIGUSR1, so a run can give its CPU to something else
+/// for a while without losing its statistics
+#[cfg(unix)]
+async fn toggle_pause_on_signal(state: Arc<SharedState>) -> Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut user1 = signal(SignalKind::user_defined1())?;
+    while user1.recv().await.is_some() {
+        if state.paused.fetch_xor(true, Ordering::Relaxed) {
+            info!("Received SIGUSR1, resuming generation");
+        } else {
+            info!("Received SIGUSR1, PAUSED; send SIGUSR1 again to resume");
+        }
+    }
+    Ok(())
+}
+
+/// The main wallet generation loop that runs in each thread
+async fn wallet_generation_loop(
+    thread_id: usize,
+    config: &Config,
+    wallet_generator: &mut WalletGenerator,
+    state: &SharedState,
+    wallet_tx: &WalletSender,
+) -> Result<()> {
+    // Counted locally so the yield decision never touches the shared counter
+    let mut attempts: u64 = 0;
+    // Attempts not yet added to the shared counter
+    let mut pending: usize = 0;
+    loop {
+        if state.shutdown.load(Ordering::Acquire) {
+            state.per_thread[thread_id].fetch_add(pending, Ordering::Relaxed);
+            state.counter.fetch_add(pending, Ordering::Relaxed);
+            return Ok(());
+        }
+        if state.paused.load(Ordering::Relaxed) {
+            // Count what was done before the pause, so the totals are current while paused
+            flush_attempts(thread_id, state, &mut pending);
+            wait_while_paused(state).await;
+            continue;
+        }
+
+        // Generate a wallet and check if its address matches the prefix and/or one of the suffixes
+        let hit = wallet_generator.try_one();
+
+        // Count locally and flush in batches so workers don't contend on the shared counter
+        pending += 1;
+        if pending == COUNTER_BATCH {
+            flush_attempts(thread_id, state, &mut pending);
+        }
+
+        let hit = hit.filter(|FoundWallet { found, .. }| {
+            config.progressive.is_none() || state.progressive_best.record_improvement(found.len)
+        });
+        if let Some(FoundWallet { wallet, found }) = hit {
+            let pattern = found.pattern;
+            let pubkey = &wallet.pubkey;
+            let Some(total_found) = state.record_find(pattern) else {
+                // Another worker found the last wallet --count asked for; it is stopping the run
+                continue;
+            };
+            let is_rarest = state.record_rarest(pubkey, &found);
+            #[cfg(any(feature = "dashboard", feature = "tui"))]
+            state.record_recent(pubkey, pattern);
+            let total_generated = state.counter.load(Ordering::Relaxed) + pending;
+
+            if state.banner {
+                print_found_banner(banner_text(config, &wallet), pattern);
+            }
+            info!("=== VANITY WALLET FOUND! ====");
+            info!("Thread: {}", thread_id);
+            info!("Public Key: {}", pubkey);
+            if let Some(ata) = &wallet.associated_token_account {
+                info!("Associated token account: {}", ata);
+            }
+            if !config.no_log_secrets {
+                let private_key = WalletGenerator::get_private_key_string(&wallet.keypair);
+                info!("Private Key: {}", private_key.as_str());
+            }
+            info!("Matched pattern: '{}'", pattern);
+            if config.prefix.is_some() {
+                info!("Matched condition: {:?}", found.condition);
+            }
+            if let Some(recovery) = &wallet.recovery {
+                if !config.no_log_secrets {
+                    info!("Mnemonic: {}", recovery.mnemonic);
+                }
+                info!("Derivation path: {}", recovery.derivation_path);
+            }
+            if is_rarest && total_found > 1 {
+                info!("New rarest find: {} matched characters", found.len);
+            }
+            info!("Total wallets generated: {}", total_generated);
+            info!("Total vanity wallets found: {}", total_found);
+            if let Some(one_in) = stats::success_rate(total_generated as u64, total_found as u64) {
+                info!("Success rate: 1 in {} wallets", one_in);
+            }
+            if let Some(target) = &config.progressive {
+                info!(
+                    "New best: {} of {} target characters",
+                    found.len,
+                    target.len()
+                );
+                if found.len == target.len() {
+                    info!("Found the whole target '{}', stopping...", target);
+                    state.request_stop(StopReason::Target);
+                }
+            }
+            if config.count == Some(total_found as u64) {
+                info!("Found {} wallets, stopping...", total_found);
+            }
+
+            // Rendering only happens on matches, so it never slows the search
+            if config.qr {
+                show_qr_code(config, pubkey);
+            }
+            if config.emit_stdout {
+                if let Err(e) = emit_found(config, &wallet) {
+                    error!("Failed to write wallet to stdout: {}", e);
+                }
+            }
+
+            info!("=== SAVING WALLET ====");
+
+            // Hand the wallet to the writer task so generation continues immediately
+            let mut wallet_doc = WalletDocument::from_wallet(&wallet, &found);
+            wallet_doc.found_at_attempt = Some(total_generated as u64);
+            if config.progressive.is_some() {
+                wallet_doc.matched_len = Some(found.len);
+            }
+            add_seed_accounts(config, &wallet, &mut wallet_doc);
+            if wallet_tx.send(wallet_doc).await.is_err() {
+                anyhow::bail!("Writer task has stopped; wallet {} was not saved", pubkey);
+            }
+        }
+
+        // Yield to the scheduler occasionally to prevent thread starvation
+        attempts += 1;
+        if attempts.is_multiple_of(config.yield_every) {
+            match &state.throttle {
+                Some(throttle) => {
+                    // The cap needs an up-to-date total, so don't hold attempts back
+                    let count = flush_attempts(thread_id, state, &mut pending);
+                    match throttle.delay(count) {
+                        Some(delay) => throttle_sleep(delay, state).await,
+                        None => tokio::task::yield_now().await,
+                    }
+                }
+                None => tokio::task::yield_now().await,
+            }
+        }
+    }
+}
+
+/// Wait for Ctrl+C or, on Unix, SIGTERM (sent by `docker stop` and most supervisors)
+async fn wait_for_shutdown_signal() -> Result<&'static str> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut terminate = signal(SignalKind::terminate())?;
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => result.map(|_| "Ctrl+C").map_err(Into::into),
+            _ = terminate.recv() => Ok("SIGTERM"),
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        tokio::signal::ctrl_c().await?;
+        Ok("Ctrl+C")
+    }
+}
+
+/// Collect the final statistics of a run
+fn summarize_run(
+    started: Instant,
+    started_at: chrono::DateTime<chrono::Utc>,
+    config: &Config,
+    state: &SharedState,
+) -> RunSummary {
+    let elapsed_secs = started.elapsed().as_secs_f64();
+    let total_generated = state.counter.load(Ordering::Relaxed) as u64;
+
+    RunSummary {
+        started_at,
+        ended_at: chrono::Utc::now(),
+        elapsed_secs,
+        total_generated,
+        total_found: state.found_wallets.load(Ordering::Relaxed) as u64,
+        found_by_pattern: state.pattern_counts(),
+        wallets_per_second: if elapsed_secs > 0.0 {
+            total_generated as f64 / elapsed_secs
+        } else {
+            0.0
+        },
+        threads: config.threads,
+        patterns: state
+            .found_by_pattern
+            .iter()
+            .map(|(pattern, _)| pattern.clone())
+            .collect(),
+        #[cfg(feature = "mongodb")]
+        collection_name: config
+            .uses_mongodb()
+            .then(|| config.collection_name.clone()),
+        #[cfg(not(feature = "mongodb"))]
+        collection_name: None,
+        rarest_find: state.rarest.lock().unwrap().clone(),
+        stop_reason: state.stop_reason.get().copied(),
+        kept_in_memory: None,
+        dropped_by_writer_queue: None,
+        rate_percentiles: state.rate.lock().unwrap().percentiles(),
+    }
+}
+
+/// Log the final statistics of a run
+fn log_summary(summary: &RunSummary, state: &SharedState) {
+    info!("=== RUN SUMMARY ====");
+    if let Some(reason) = summary.stop_reason {
+        info!("Stopped by: {:?}", reason);
+    }
+    info!("Elapsed: {:.1} seconds", summary.elapsed_secs);
+    info!("Total wallets generated: {}", summary.total_generated);
+    info!("Total vanity wallets found: {}", summary.total_found);
+    if let Some(breakdown) = state.pattern_breakdown() {
+        info!("Found by pattern: {}", breakdown);
+    }
+    if let Some(one_in) = stats::success_rate(summary.total_generated, summary.total_found) {
+        info!("Success rate: 1 in {} wallets", one_in);
+    }
+    if let Some(rarest) = &summary.rarest_find {
+        info!(
+            "Rarest find: {} ('{}', {} matched characters)",
+            rarest.public_key, rarest.matched_pattern, rarest.matched_len
+        );
+    }
+    if let Some(kept) = summary.kept_in_memory {
+        info!("Wallets kept in memory (not saved): {}", kept);
+    }
+    if let Some(dropped) = summary
+        .dropped_by_writer_queue
+        .filter(|&dropped| dropped > 0)
+    {
+        warn!(
+            "Wallets dropped by the full writer queue (not saved): {}",
+            dropped
+        );
+    }
+    if summary.elapsed_secs > 0.0 {
+        info!(
+            "Average rate: {:.2} wallets/second",
+            summary.wallets_per_second
+        );
+    }
+    if let Some(percentiles) = &summary.rate_percentiles {
+        info!("Rate percentiles: {}", percentiles);
+    }
+}
+
+/// Find exactly one vanity wallet as fast as possible, save it and exit
+async fn run_single(
+    config: &Arc<Config>,
+    wallet_generator: WalletGenerator,
+    sink: Box<dyn WalletSink>,
+    banner: bool,
+) -> Result<()> {
+    let threads = config.threads;
+    let (wallet, mut wallet_doc) = tokio::task::spawn_blocking(move || {
+        let (wallet, attempts) = find_vanity_wallet(&wallet_generator, threads);
+        let found = wallet_generator
+            .find_match(&wallet)
+            .expect("find_vanity_wallet only returns matching wallets");
+        let mut wallet_doc = WalletDocument::from_wallet(&wallet, &found);
+        wallet_doc.found_at_attempt = Some(attempts);
+        (wallet, wallet_doc)
+    })
+    .await?;
+    add_seed_accounts(config, &wallet, &mut wallet_doc);
+    let pubkey = &wallet.pubkey;
+
+    if banner {
+        print_found_banner(banner_text(config, &wallet), &wallet_doc.matched_pattern);
+    }
+    info!("=== VANITY WALLET FOUND! ====");
+    info!("Public Key: {}", pubkey);
+    if let Some(ata) = &wallet.associated_token_account {
+        info!("Associated token account: {}", ata);
+    }
+    if !config.no_log_secrets {
+        info!(
+            "Private Key: {}",
+            WalletGenerator::get_private_key_string(&wallet.keypair).as_str()
+        );
+    }
+    if let Some(recovery) = &wallet.recovery {
+        if !config.no_log_secrets {
+            info!("Mnemonic: {}", recovery.mnemonic);
+        }
+        info!("Derivation path: {}", recovery.derivation_path);
+    }
+
+    if config.qr {
+        show_qr_code(config, pubkey);
+    }
+    if config.emit_stdout {
+        if let Err(e) = emit_found(config, &wallet) {
+            error!("Failed to write wallet to stdout: {}", e);
+        }
+    }
+
+    info!("=== SAVING WALLET ====");
+    let mut writer = WalletWriter::new(config.clone(), sink)?;
+    let result = writer.save(&wallet_doc).await;
+    writer.finish().await;
+    Ok(result?)
+}
+
+/// Check the pattern and settings before starting
+fn validate_config(config: &Config) -> Result<()> {
+    if config.suffix.is_empty() && config.fuzzy.is_none() && config.progressive.is_none() {
+        anyhow::bail!("At least one --suffix is required");
+    }
+    if let Some(target) = &config.progressive {
+        // The config file gets around clap's conflicts, so check them again
+        if !config.suffix.is_empty()
+            || config.fuzzy.is_some()
+            || config.prefix.is_some()
+            || config.single
+        {
+            anyhow::bail!(
+                "--progressive can't be combined with --suffix, --fuzzy, --prefix or --single"
+            );
+        }
+        if config.log_near_misses.is_some() {
+            anyhow::bail!("--log-near-misses can't be combined with --progressive");
+        }
+        matcher::validate_pattern(target, config.alphabet)?;
+    }
+    for suffix in &config.suffix {
+        matcher::validate_pattern(suffix, config.alphabet)?;
+    }
+    if let Some(fuzzy) = &config.fuzzy {
+        matcher::validate_pattern(&fuzzy.target, config.alphabet)?;
+        // Within that distance the empty string already matches, and so does every address
+        if fuzzy.max_distance >= fuzzy.target.len() {
+            anyhow::bail!(
+                "--max-distance {} would let every address match '{}'; it must be below the target's length",
+                fuzzy.max_distance,
+                fuzzy.target
+            );
+        }
+    }
+    match (config.min_match_len, &config.fuzzy) {
+        (Some(0), _) => anyhow::bail!("--min-match-len must be at least 1"),
+        (Some(min), Some(fuzzy)) if min > fuzzy.target.len() => anyhow::bail!(
+            "--min-match-len {} is longer than the --fuzzy target '{}', so nothing could match",
+            min,
+            fuzzy.target
+        ),
+        (Some(_), None) => anyhow::bail!("--min-match-len needs --fuzzy"),
+        _ => {}
+    }
+    if let Some(prefix) = &config.prefix {
+        if config.fuzzy.is_some() {
+            anyhow::bail!("--prefix can't be combined with --fuzzy");
+        }
+        matcher::validate_pattern(prefix, config.alphabet)?;
+    }
+    if config.smart_case && config.fuzzy.is_some() {
+        anyhow::bail!("--smart-case can't be combined with --fuzzy");
+    }
+    if let Some(before) = &config.before_suffix {
+        if config.fuzzy.is_some() {
+            anyhow::bail!("--before-suffix-chars can't be combined with --fuzzy");
+        }
+        if before.len == 0 {
+            anyhow::bail!("--before-suffix-len must be at least 1");
+        }
+        matcher::validate_pattern(&before.allowed_chars, config.alphabet)?;
+    }
+
+    if !(config.rate_ema_alpha > 0.0 && config.rate_ema_alpha <= 1.0) {
+        anyhow::bail!(
+            "--rate-ema-alpha must be in (0, 1], got {}",
+            config.rate_ema_alpha
+        );
+    }
+
+    if config.count == Some(0) {
+        anyhow::bail!("--count must be at least 1");
+    }
+    if config.derivation_scan == Some(0) {
+        anyhow::bail!("--derivation-scan must be at least 1");
+    }
+    // As with --progressive, a config file gets around clap's conflicts
+    if config.binary_output.is_some()
+        && (config.derivation_scan.is_some() || config.match_ata.is_some())
+    {
+        anyhow::bail!(
+            "--binary-output can't be combined with --mnemonic or --match-ata; its records only hold the keypair"
+        );
+    }
+    if config.group_by_seed && config.derivation_scan.is_none() {
+        anyhow::bail!("--group-by-seed needs --mnemonic");
+    }
+    if config.compress && config.output_file.is_none() {
+        anyhow::bail!("--compress needs --output-file");
+    }
+    if config.progress_every_secs == Some(0) {
+        anyhow::bail!("--progress-every-secs must be at least 1");
+    }
+    if config.save_timeout_secs == 0 {
+        anyhow::bail!("--save-timeout-secs must be at least 1");
+    }
+    if config.writer_queue_size == 0 {
+        anyhow::bail!("--writer-queue-size must be at least 1");
+    }
+    if config.count_by_pattern_interval_secs == 0 {
+        anyhow::bail!("--count-by-pattern-interval-secs must be at least 1");
+    }
+    if config.single && (config.count.is_some() || config.max_runtime.is_some()) {
+        anyhow::bail!("--single can't be combined with --count or --max-runtime");
+    }
+
+    if let Some(max_rate) = config.max_rate {
+        if !(max_rate > 0.0 && max_rate.is_finite()) {
+            anyhow::bail!("--max-rate must be a positive number, got {}", max_rate);
+        }
+    }
+
+    if config.match_private && config.match_ata.is_some() {
+        anyhow::bail!("--match-private and --match-ata pick different strings to match; use one");
+    }
+    if let Some(mint) = &config.match_ata {
+        if mint.parse::<Pubkey>().is_err() {
+            anyhow::bail!("--match-ata '{}' is not a valid mint address", mint);
+        }
+    }
+
+    if let Some(max) = config.max_repeat {
+        if max == 0 {
+            anyhow::bail!("--max-repeat must be at least 1");
+        }
+        // A pattern with a longer run than allowed could never be accepted
+        let mut patterns = config.suffix.iter().chain(&config.prefix);
+        if let Some(pattern) = patterns.find(|p| matcher::longest_run(p) > max) {
+            anyhow::bail!(
+                "Pattern '{}' repeats a character more than --max-repeat {} times in a row",
+                pattern,
+                max
+            );
+        }
+    }
+    if config.min_distinct.is_some_and(|min| min == 0 || min > 58) {
+        anyhow::bail!("--min-distinct must be between 1 and 58");
+    }
+    if let Some(min) = config.min_entropy {
+        // No address is longer than 44 characters, which caps the entropy at log2(44)
+        if !(min > 0.0 && min <= 44f64.log2()) {
+            anyhow::bail!(
+                "--min-entropy must be above 0 and at most {:.2} bits, got {}",
+                44f64.log2(),
+                min
+            );
+        }
+    }
+    if let Some(short) = config.log_near_misses {
+        if short == 0 {
+            anyhow::bail!("--log-near-misses must be at least 1");
+        }
+        if config.fuzzy.is_some() {
+            anyhow::bail!("--log-near-misses can't be combined with --fuzzy");
+        }
+    }
+
+    for excluded in &config.exclude {
+        if excluded.is_empty() {
+            anyhow::bail!(
+                "--exclude was given an empty substring, which would reject every address"
+            );
+        }
+        // A pattern containing an exclusion could never be accepted
+        let mut patterns = config.suffix.iter().chain(&config.prefix);
+        if let Some(pattern) = patterns.find(|p| matcher::contains_ignore_case(p, excluded)) {
+            anyhow::bail!(
+                "Pattern '{}' contains the excluded substring '{}', so it could never match",
+                pattern,
+                excluded
+            );
+        }
+    }
+
+    if let (Some(min), Some(max)) = (config.min_address_len, config.max_address_len) {
+        if min > max {
+            anyhow::bail!(
+                "--min-address-len ({}) exceeds --max-address-len ({})",
+                min,
+                max
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Seconds each candidate thread count is measured for by --auto-threads
+const AUTO_THREADS_SAMPLE_SECS: u64 = 3;
+
+/// Measure 1, N/2 and N threads (N being --threads) and return the fastest count
+async fn auto_tune_threads(config: &Config) -> Result<usize> {
+    let max_threads = config.threads.max(1);
+    let mut candidates = vec![1, max_threads / 2, max_threads];
+    candidates.retain(|&threads| threads > 0);
+    candidates.dedup();
+    info!(
+        "Benchmarking {:?} threads for {} seconds each...",
+        candidates, AUTO_THREADS_SAMPLE_SECS
+    );
+
+    let wallet_generator = build_wallet_generator(config);
+    let rates = tokio::task::spawn_blocking(move || {
+        candidates
+            .into_iter()
+            .map(|threads| {
+                let duration = std::time::Duration::from_secs(AUTO_THREADS_SAMPLE_SECS);
+                (
+                    threads,
+                    measure_throughput(&wallet_generator, threads, duration),
+                )
+            })
+            .collect::<Vec<_>>()
+    })
+    .await?;
+
+    for (threads, rate) in &rates {
+        info!("  - {} thread(s): {:.2} wallets/second", threads, rate);
+    }
+    // On a tie the larger count wins, as max_by keeps the last maximum
+    let (threads, rate) = rates
+        .into_iter()
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .expect("there is always at least one candidate");
+    info!(
+        "Auto-selected {} threads ({:.2} wallets/second)",
+        threads, rate
+    );
+    Ok(threads)
+}
+
+/// Drop repeated suffixes and warn about ones a shorter suffix already covers, so
+/// they don't make the per-pattern counts confusing
+fn simplify_patterns(config: &mut Config) {
+    for (repeated, kept) in matcher::dedup_patterns(&mut config.suffix, config.smart_case) {
+        if repeated == kept {
+            warn!(
+                "Suffix '{}' was given more than once; searching for it once",
+                repeated
+            );
+        } else {
+            warn!(
+                "Suffix '{}' is redundant: with --smart-case '{}' already matches it in any case; searching for '{}' only",
+                repeated, kept, kept
+            );
+        }
+    }
+    if config.progressive.is_some() {
+        return;
+    }
+    for (narrow, broad) in build_match_config(config).subsumed_suffixes() {
+        warn!(
+            "Suffix '{}' is redundant: every address ending with it also ends with '{}', so it only splits that suffix's finds",
+            narrow, broad
+        );
+    }
+}
+
+/// Build the pattern and filters wallets are matched against
+fn build_match_config(config: &Config) -> MatchConfig {
+    MatchConfig {
+        fuzzy: config.fuzzy.clone(),
+        min_match_len: config.min_match_len,
+        before_suffix: config.before_suffix.clone(),
+        prefix: config.prefix.clone(),
+        smart_case: config.smart_case,
+        mode: config.match_mode,
+        forbidden_chars: config.forbidden_chars.clone(),
+        required_chars: config.required_chars.clone(),
+        min_address_len: config.min_address_len,
+        max_address_len: config.max_address_len,
+        max_repeat: config.max_repeat,
+        min_distinct: config.min_distinct,
+        min_entropy: config.min_entropy,
+        exclude: config.exclude.clone(),
+        target: if config.match_private {
+            MatchTarget::PrivateKey
+        } else {
+            MatchTarget::PublicKey
+        },
+        ..MatchConfig::new(&match &config.progressive {
+            Some(target) => matcher::target_tails(target),
+            None => config.suffix.clone(),
+        })
+    }
+}
+
+/// Build the wallet generator for the configured pattern and filters
+fn build_wallet_generator(config: &Config) -> WalletGenerator {
+    let mut wallet_generator = WalletGenerator::new(build_match_config(config))
+        .with_require_on_curve(config.require_on_curve);
+    if let Some(mint) = config
+        .match_ata
+        .as_deref()
+        .and_then(|mint| mint.parse().ok())
+    {
+        wallet_generator = wallet_generator.with_ata_mint(mint);
+    }
+    if let Some(max_short) = config.log_near_misses {
+        wallet_generator = wallet_generator.with_near_misses(max_short);
+    }
+    if config.group_by_seed {
+        wallet_generator = wallet_generator.with_group_by_seed();
+    }
+    match config.derivation_scan {
+        Some(accounts) => wallet_generator.with_mnemonic(accounts),
+        None => wallet_generator,
+    }
+}
+
+/// With --group-by-seed, list every scanned account of the wallet's mnemonic in its document
+fn add_seed_accounts(config: &Config, wallet: &GeneratedWallet, wallet_doc: &mut WalletDocument) {
+    let (true, Some(accounts), Some(recovery)) = (
+        config.group_by_seed,
+        config.derivation_scan,
+        &wallet.recovery,
+    ) else {
+        return;
+    };
+    let accounts = WalletGenerator::seed_accounts(&recovery.mnemonic, accounts);
+    wallet_doc.accounts = Some(accounts.iter().map(SeedAccount::from_wallet).collect());
+}
+
+/// Log the lines a found wallet produces, for --simulate. Nothing is generated: the
+/// keys are padded with '0', which base58 never uses, so they can't be mistaken for
+/// (or imported as) real ones.
+fn simulate_find(suffix: &str, alphabet: Base58Alphabet) -> Result<()> {
+    matcher::validate_pattern(suffix, alphabet)?;
+    let public_key = format!(
+        "{}{}",
+        "0".repeat(44usize.saturating_sub(suffix.len())),
+        suffix
+    );
+    let private_key = format!("SIMULATED0NOT0A0REAL0KEY{}", "0".repeat(64));
+
+    warn!("*** SIMULATED OUTPUT: no wallet was generated and the keys below are fake ***");
+    info!("=== VANITY WALLET FOUND! ====");
+    info!("Thread: 0");
+    info!("Public Key: {}", public_key);
+    info!("Private Key: {}", private_key);
+    info!("Matched pattern: '{}'", suffix);
+    info!("Total wallets generated: 1");
+    info!("Total vanity wallets found: 1");
+    info!("Success rate: 1 in 1 wallets");
+    info!("=== SAVING WALLET ====");
+    warn!("*** END OF SIMULATED OUTPUT: nothing was saved ***");
+
+    Ok(())
+}
+
+/// Seconds of throughput measured by --estimate-only
+const ESTIMATE_SAMPLE_SECS: u64 = 3;
+
+/// Percentiles of the time to the first match printed by --estimate-only
+const ESTIMATE_QUANTILES: [f64; 3] = [0.5, 0.9, 0.99];
+
+/// Measure the rate and print how long the first match is likely to take, for --estimate-only
+async fn print_estimate(config: &Config) -> Result<()> {
+    let wallet_generator = build_wallet_generator(config);
+    let Some(attempts) = wallet_generator.estimated_attempts() else {
+        println!("No estimates are available for fuzzy matching");
+        return Ok(());
+    };
+
+    info!(
+        "Measuring throughput on {} threads for {} seconds...",
+        config.threads, ESTIMATE_SAMPLE_SECS
+    );
+    let threads = config.threads;
+    let rate = tokio::task::spawn_blocking(move || {
+        measure_throughput(
+            &wallet_generator,
+            threads,
+            std::time::Duration::from_secs(ESTIMATE_SAMPLE_SECS),
+        )
+    })
+    .await?;
+    if rate <= 0.0 {
+        anyhow::bail!("No wallets were generated during the sample");
+    }
 
-    /// MongoDB collection name
-    #[arg(long, default_value = "wallets")]
-    collection_name: String,
+    println!("{:<19}{:.2} wallets/second", "Rate:", rate);
+    println!("{:<19}{:.0} per match", "Expected attempts:", attempts);
+    println!(
+        "{:<19}{}",
+        "Mean time:",
+        stats::format_duration(attempts / rate)
+    );
+    // The mean hides how spread out a geometric distribution is, so show percentiles too
+    for quantile in ESTIMATE_QUANTILES {
+        let needed = stats::attempts_quantile(1.0 / attempts, quantile);
+        println!(
+            "{:<19}{}",
+            format!("p{} time:", quantile * 100.0),
+            stats::format_duration(needed / rate)
+        );
+    }
+    println!("Character and length filters aren't included, so real searches take somewhat longer");
 
-    /// The suffix to search for in wallet addresses
-    #[arg(short, long, default_value = "pump")]
-    suffix: String,
+    Ok(())
 }
 
-/// The main wallet generation loop that runs in each thread
-async fn wallet_generation_loop(
-    thread_id: usize,
-    wallet_generator: &WalletGenerator,
-    counter: &Arc<AtomicUsize>,
-    found_wallets: &Arc<AtomicUsize>,
-    db_client: &Arc<Mutex<MongoDBClient>>,
-) -> Result<()> {
-    loop {
-        // Generate a wallet
-        let wallet = wallet_generator.generate_wallet();
+/// Print a table of how likely each pattern is to match, for --explain
+fn print_explanation(config: &Config) {
+    let match_config = build_match_config(config);
+    let Some(estimates) = match_config.pattern_estimates() else {
+        println!("No estimates are available for fuzzy matching");
+        return;
+    };
+    let total: f64 = estimates.iter().map(|estimate| estimate.probability).sum();
+    let width = estimates
+        .iter()
+        .map(|estimate| estimate.pattern.chars().count())
+        .max()
+        .unwrap_or(0)
+        .max("PATTERN".len());
 
-        // Increment counter
-        let count = counter.fetch_add(1, Ordering::SeqCst);
+    println!(
+        "{:<width$}  {:<9}  {:>6}  {:>11}  {:>16}  {:>8}",
+        "PATTERN", "MATCHES", "LENGTH", "PROBABILITY", "ONE IN", "SHARE"
+    );
+    for estimate in &estimates {
+        println!(
+            "{:<width$}  {:<9}  {:>6}  {:>11.3e}  {:>16.0}  {:>7.3}%",
+            estimate.pattern,
+            format!("{:?}", estimate.condition).to_lowercase(),
+            estimate.len,
+            estimate.probability,
+            1.0 / estimate.probability,
+            100.0 * estimate.probability / total
+        );
+    }
+    if let Some(attempts) = match_config.estimated_attempts() {
+        println!();
+        println!("Any pattern: about one in {:.0} addresses", attempts);
+    }
+    println!("Share is each pattern's part of the total hit rate; character and length filters aren't included");
+}
 
-        // Print progress every 100000 wallets
-        if count % 100000 == 0 {
-            let total_found = found_wallets.load(Ordering::SeqCst);
-            let wallets_per_second = 100000.0 / 10.0; // Approximate, assuming 10 seconds per 100000 wallets
+/// Run a subcommand instead of a search
+async fn run_command(command: Command, config: &Config) -> Result<()> {
+    match command {
+        Command::Doctor { sample_secs } => run_doctor(config, sample_secs).await?,
+        Command::Import { file } => import_binary(config, &file).await?,
+        Command::Resume { file } => resume_spill(config, &file).await?,
+        Command::Selftest => run_selftest()?,
+        #[cfg(feature = "mongodb")]
+        Command::Export { format, out } => {
+            connect_mongodb(config, None)
+                .await?
+                .export(format, &out)
+                .await?;
+        }
+        #[cfg(feature = "mongodb")]
+        Command::List { pattern, limit } => {
+            let db_client = connect_mongodb(config, None).await?;
+            for wallet in db_client.list_by_pattern(&pattern, limit).await? {
+                println!("{}  {}", wallet.created_at.to_rfc3339(), wallet.public_key);
+            }
+        }
+        #[cfg(feature = "mongodb")]
+        Command::Verify => {
+            let report = connect_mongodb(config, None).await?.verify().await?;
+            info!(
+                "Verified {} stored wallets, {} failed",
+                report.verified, report.failed
+            );
+            if report.failed > 0 {
+                anyhow::bail!("{} stored wallet(s) failed verification", report.failed);
+            }
+        }
+        #[cfg(feature = "mongodb")]
+        Command::Migrate => {
+            use solana_vanity_wallet::sink::SCHEMA_VERSION;
 
-            info!("=== PROGRESS UPDATE ====");
-            info!("Thread: {}", thread_id);
-            info!("Generated: {} wallets", count);
-            info!("Found: {} vanity wallets", total_found);
-            if total_found > 0 {
-                info!("Success rate: 1 in {} wallets", count / total_found);
+            let versions = connect_mongodb(config, None)
+                .await?
+                .schema_versions()
+                .await?;
+            for (version, count) in &versions {
+                info!("Schema version {}: {} wallets", version, count);
             }
+            let outdated: u64 = versions
+                .iter()
+                .filter(|(version, _)| *version < SCHEMA_VERSION)
+                .map(|(_, count)| count)
+                .sum();
             info!(
-                "Performance: ~{:.2} wallets/second (~{:.2} million wallets/hour)",
-                wallets_per_second,
-                wallets_per_second * 3600.0 / 1_000_000.0
+                "Current schema version is {}; {} wallets are older, and none of them need migrating",
+                SCHEMA_VERSION, outdated
             );
-            info!("=== CONTINUING SEARCH ====");
         }
+        #[cfg(feature = "mongodb")]
+        Command::Delete { pattern, yes } => {
+            let db_client = connect_mongodb(config, None).await?;
+            let count = db_client.count_by_pattern(&pattern).await?;
+            info!("{} stored wallets match pattern '{}'", count, pattern);
+            if !yes {
+                info!("Re-run with --yes to delete them");
+            } else if count > 0 {
+                db_client.delete_by_pattern(&pattern).await?;
+            }
+        }
+    }
 
-        // Check if wallet address ends with the suffix
-        if wallet_generator.is_vanity_wallet(&wallet) {
-            let pubkey = wallet.pubkey().to_string();
-            let private_key = WalletGenerator::get_private_key_string(&wallet);
-            let total_found = found_wallets.fetch_add(1, Ordering::SeqCst) + 1;
-            let total_generated = counter.load(Ordering::SeqCst);
+    Ok(())
+}
 
-            info!("=== VANITY WALLET FOUND! ====");
-            info!("Thread: {}", thread_id);
-            info!("Public Key: {}", pubkey);
-            info!("Private Key: {}", private_key);
-            info!("Wallet ends with 'pump' (lowercase)");
-            info!("Total wallets generated: {}", total_generated);
-            info!("Total vanity wallets found: {}", total_found);
-            info!(
-                "Success rate: 1 in {} wallets",
-                total_generated / total_found
+/// Save every wallet in a --binary-output file to the configured sinks that don't
+/// hold it yet
+async fn import_binary(config: &Config, path: &std::path::Path) -> Result<()> {
+    // Appending each imported record to the file being read would never reach its end
+    if let Some(output) = &config.binary_output {
+        let same_file = matches!(
+            (std::fs::canonicalize(output), std::fs::canonicalize(path)),
+            (Ok(output), Ok(input)) if output == input
+        );
+        if same_file {
+            anyhow::bail!(
+                "{} is both the --binary-output file and the file being imported; drop --binary-output for the import",
+                path.display()
             );
-            info!("=== SAVING TO DATABASE ====");
+        }
+    }
 
-            // Save wallet to MongoDB with error handling
-            let mut retry_count = 0;
-            const MAX_RETRIES: usize = 3;
+    let match_config = build_match_config(config);
+    let mut sink = create_sink(config).await?;
+    let mut imported = 0;
+    let mut skipped = 0;
+    let mut unmatched = 0;
+    let mut failed = 0;
 
-            while retry_count < MAX_RETRIES {
-                match db_client.lock().await.save_wallet(&wallet).await {
-                    Ok(_) => {
-                        info!("Wallet successfully saved to MongoDB");
-                        break; // Success, exit retry loop
-                    }
-                    Err(e) => {
-                        retry_count += 1;
-                        if retry_count >= MAX_RETRIES {
-                            error!(
-                                "Failed to save wallet to MongoDB after {} retries: {}",
-                                MAX_RETRIES, e
-                            );
-                        } else {
-                            warn!(
-                                "MongoDB save attempt {} failed: {}. Retrying...",
-                                retry_count, e
-                            );
-                            tokio::time::sleep(tokio::time::Duration::from_millis(
-                                500 * retry_count as u64,
-                            ))
-                            .await;
-                        }
-                    }
-                }
+    for wallet in BinaryReader::open(path)? {
+        let mut wallet = wallet?;
+        if sink.contains(&wallet.public_key).await? {
+            skipped += 1;
+            continue;
+        }
+        // Records don't store how they matched, so match them again with the current flags
+        match match_config.find_match(&wallet.public_key) {
+            Some(found) => {
+                wallet.matched_pattern = found.pattern.to_string();
+                wallet.matched_condition = Some(found.condition);
+                wallet.vanity_score = matcher::vanity_score(&wallet.public_key, &found);
             }
+            None => unmatched += 1,
         }
+        match save_with_retries(config, sink.as_mut(), &wallet).await {
+            Ok(()) => imported += 1,
+            Err(e) => {
+                error!("Failed to save wallet {}: {}", wallet.public_key, e);
+                failed += 1;
+            }
+        }
+    }
+    sink.flush().await?;
 
-        // Yield to the scheduler occasionally to prevent thread starvation
-        if counter.load(Ordering::SeqCst) % 1000 == 0 {
-            tokio::task::yield_now().await;
+    info!(
+        "Imported {} wallets from {}, skipped {} already stored",
+        imported,
+        path.display(),
+        skipped
+    );
+    if unmatched > 0 {
+        warn!(
+            "{} of them don't match the given pattern flags and were saved without a matched_pattern",
+            unmatched
+        );
+    }
+    if failed > 0 {
+        // Sinks that can tell what they hold skip it next time; file sinks can't, and
+        // get the already imported records again
+        anyhow::bail!(
+            "{} wallets could not be saved; run import on {} again to retry them",
+            failed,
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+/// Save the wallets of a spill file that the sinks don't hold yet, for the resume subcommand
+async fn resume_spill(config: &Config, path: &std::path::Path) -> Result<()> {
+    let spilled = read_wallet_file(path)?;
+    let mut sink = create_sink(config).await?;
+    let mut resumed = 0;
+    let mut skipped = 0;
+    let mut failed = 0;
+
+    for wallet in &spilled {
+        if sink.contains(&wallet.public_key).await? {
+            skipped += 1;
+            continue;
+        }
+        match save_with_retries(config, sink.as_mut(), wallet).await {
+            Ok(()) => resumed += 1,
+            Err(e) => {
+                error!("Failed to save wallet {}: {}", wallet.public_key, e);
+                failed += 1;
+            }
         }
     }
+    sink.flush().await?;
+
+    info!(
+        "Resumed {} wallets from {}, skipped {} already stored",
+        resumed,
+        path.display(),
+        skipped
+    );
+    if failed > 0 {
+        // The file is never modified, so running resume again retries exactly these
+        anyhow::bail!(
+            "{} wallets could not be saved; keep {} and run resume again",
+            failed,
+            path.display()
+        );
+    }
+    info!(
+        "Every wallet in {} is stored; the file can be deleted",
+        path.display()
+    );
+    Ok(())
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    // Initialize logger
-    env_logger::init();
+#[cfg(feature = "mongodb")]
+async fn connect_mongodb(
+    config: &Config,
+    ttl: Option<std::time::Duration>,
+) -> Result<MongoDBClient> {
+    MongoDBClient::new(
+        config.mongodb_uri(),
+        &config.db_name,
+        &config.collection_name,
+        config.write_concern,
+        ttl,
+    )
+    .await
+    .map_err(Into::into)
+}
+
+/// Run the built-in invariant checks, failing if any of them did
+fn run_selftest() -> Result<()> {
+    let checks = selftest::run();
+    let mut failures = 0;
+    for check in &checks {
+        match &check.result {
+            Ok(()) => info!("[ok] {}", check.name),
+            Err(e) => {
+                error!("[failed] {}: {}", check.name, e);
+                failures += 1;
+            }
+        }
+    }
+    if failures > 0 {
+        anyhow::bail!("{} of {} self-test checks failed", failures, checks.len());
+    }
+    info!("All {} self-test checks passed", checks.len());
+    Ok(())
+}
+
+/// Check the environment end-to-end, failing if any check does
+async fn run_doctor(config: &Config, sample_secs: u64) -> Result<()> {
+    let mut failures = 0;
+    info!("=== DOCTOR ====");
+
+    match validate_config(config) {
+        Ok(()) => info!("[ok] Pattern and settings are valid"),
+        Err(e) => {
+            error!("[failed] {}", e);
+            failures += 1;
+        }
+    }
+
+    if let Some(path) = config.output_path() {
+        info!("[skipped] Sink check, wallets go to {}", path.display());
+    }
+    if let Some(path) = &config.binary_output {
+        info!("[skipped] Sink check, wallets go to {}", path.display());
+    }
+    if let Some(url) = &config.nats_url {
+        info!("[skipped] Sink check, wallets go to NATS at {}", url);
+    }
+    if let Some(path) = &config.sqlite_file {
+        match open_sqlite(config, path) {
+            Ok(_) => info!("[ok] Unlocked encrypted database {}", path.display()),
+            Err(e) => {
+                error!("[failed] {:#}", e);
+                failures += 1;
+            }
+        }
+    }
+    #[cfg(feature = "mongodb")]
+    if config.uses_mongodb() {
+        match connect_mongodb(config, None).await {
+            Ok(_) => info!("[ok] MongoDB at {} answered a ping", config.mongodb_uri()),
+            Err(e) => {
+                error!("[failed] Could not reach MongoDB: {}", e);
+                failures += 1;
+            }
+        }
+    }
+    #[cfg(not(feature = "mongodb"))]
+    if !config.has_other_sink() {
+        error!("[failed] Built without MongoDB support; use --output-file or --nats-url");
+        failures += 1;
+    }
+
+    info!(
+        "Measuring throughput on {} threads for {} seconds...",
+        config.threads, sample_secs
+    );
+    let wallet_generator = build_wallet_generator(config);
+    let estimated_attempts = wallet_generator.estimated_attempts();
+    let threads = config.threads;
+    let rate = tokio::task::spawn_blocking(move || {
+        measure_throughput(
+            &wallet_generator,
+            threads,
+            std::time::Duration::from_secs(sample_secs),
+        )
+    })
+    .await?;
+
+    if rate > 0.0 {
+        info!("[ok] Throughput: ~{:.2} wallets/second", rate);
+        match estimated_attempts {
+            Some(attempts) => info!(
+                "Expected ~{:.0} attempts per match, about {} to the first match",
+                attempts,
+                stats::format_duration(attempts / rate)
+            ),
+            None => info!("No time estimate is available for fuzzy matching"),
+        }
+    } else {
+        error!("[failed] No wallets were generated during the sample");
+        failures += 1;
+    }
+
+    if failures > 0 {
+        anyhow::bail!("{} doctor check(s) failed", failures);
+    }
+    info!("All checks passed");
+    Ok(())
+}
+
+/// Build the wallet sink selected by the configuration
+async fn create_sink(config: &Config) -> Result<Box<dyn WalletSink>> {
+    let mut sinks: Vec<Box<dyn WalletSink>> = Vec::new();
+
+    #[cfg(feature = "mongodb")]
+    if config.uses_mongodb() {
+        let ttl = config.ttl_seconds.map(std::time::Duration::from_secs);
+        let db_client = connect_mongodb(config, ttl).await?;
+        info!(
+            "Collection currently holds {} wallets",
+            db_client.get_wallet_count().await?
+        );
+        sinks.push(Box::new(db_client));
+    }
+
+    if let Some(path) = config.output_path() {
+        sinks.push(Box::new(if config.compress {
+            FileSink::new_gzip(&path)?
+        } else {
+            FileSink::new(&path)?
+        }));
+    }
+
+    if let Some(path) = &config.binary_output {
+        sinks.push(Box::new(BinarySink::new(path)?));
+    }
+
+    if let Some(url) = &config.nats_url {
+        sinks.push(Box::new(
+            NatsSink::new(url, &config.nats_subject, config.nats_include_private_key).await?,
+        ));
+    }
+
+    if let Some(path) = &config.sqlite_file {
+        sinks.push(open_sqlite(config, path)?);
+    }
+
+    match sinks.len() {
+        0 => anyhow::bail!("No wallet sink configured; pass --output-file, --binary-output, --nats-url or --sqlite-file (built without MongoDB support)"),
+        1 => Ok(sinks.pop().expect("one sink")),
+        _ => Ok(Box::new(MultiSink::new(sinks))),
+    }
+}
+
+/// Open the encrypted SQLite database, or explain why this build can't
+#[cfg(feature = "sqlcipher")]
+fn open_sqlite(config: &Config, path: &std::path::Path) -> Result<Box<dyn WalletSink>> {
+    let key = config
+        .sqlite_key
+        .as_deref()
+        .ok_or_else(|| anyhow::anyhow!("--sqlite-file needs a passphrase in --sqlite-key"))?;
+    Ok(Box::new(SqliteSink::open(path, key)?))
+}
+
+#[cfg(not(feature = "sqlcipher"))]
+fn open_sqlite(_config: &Config, _path: &std::path::Path) -> Result<Box<dyn WalletSink>> {
+    anyhow::bail!(
+        "--sqlite-file needs SQLCipher support; rebuild with `cargo build --release --features sqlcipher`"
+    )
+}
+
+/// Build the Tokio runtime, optionally pinning each of its threads to a CPU core
+fn build_runtime(pin_threads: bool) -> Result<tokio::runtime::Runtime> {
+    let mut builder = tokio::runtime::Builder::new_multi_thread();
+    builder.enable_all();
+
+    if pin_threads {
+        match core_affinity::get_core_ids() {
+            Some(core_ids) if !core_ids.is_empty() => {
+                info!(
+                    "Pinning runtime threads across {} CPU cores",
+                    core_ids.len()
+                );
+                // Worker threads start first, so each of them lands on a distinct core
+                let next_core = AtomicUsize::new(0);
+                builder.on_thread_start(move || {
+                    let index = next_core.fetch_add(1, Ordering::Relaxed);
+                    let core_id = core_ids[index % core_ids.len()];
+                    if !core_affinity::set_for_current(core_id) {
+                        warn!("Failed to pin thread to CPU core {}", core_id.id);
+                    }
+                });
+            }
+            _ => warn!("CPU affinity is not supported on this platform, running unpinned"),
+        }
+    }
+
+    Ok(builder.build()?)
+}
 
+fn main() -> Result<()> {
     // Load environment variables from .env file if it exists
     dotenv::dotenv().ok();
 
-    // Parse command line arguments
-    let cli = Cli::parse();
+    // Parse command line arguments, remembering which ones were actually given
+    // so a config file can fill in the rest
+    let matches = Cli::command().get_matches();
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    let explicit_args = matches
+        .ids()
+        .filter(|id| {
+            matches!(
+                matches.value_source(id.as_str()),
+                Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+            )
+        })
+        .map(|id| id.to_string())
+        .collect::<HashSet<_>>();
+
+    // Initialize logger
+    logging::init_logging(
+        cli.log_file.as_deref(),
+        cli.log_max_size_mb,
+        cli.quiet,
+        cli.wants_tui(),
+    )?;
+
+    build_runtime(cli.pin_threads)?.block_on(run(cli, explicit_args))
+}
+
+async fn run(cli: Cli, explicit_args: HashSet<String>) -> Result<()> {
+    // The banner is for people watching a terminal, never for logs or pipes
+    let wants_tui = cli.wants_tui();
+    let banner = !cli.no_color && !cli.quiet && !wants_tui && std::io::stderr().is_terminal();
 
     // Create configuration
-    let config = Config {
+    let mut config = Config {
         threads: cli.threads,
-        mongodb_uri: cli.mongodb_uri.unwrap_or_else(|| {
-            std::env::var("MONGODB_URI").unwrap_or_else(|_| "mongodb://localhost:27017".to_string())
-        }),
+        #[cfg(feature = "mongodb")]
+        mongodb_uri: cli.mongodb_uri,
+        #[cfg(feature = "mongodb")]
         db_name: cli.db_name,
+        #[cfg(feature = "mongodb")]
         collection_name: cli.collection_name,
+        #[cfg(feature = "mongodb")]
+        collection_suffix_timestamp: cli.collection_suffix_timestamp,
+        #[cfg(feature = "mongodb")]
+        write_concern: cli.write_concern,
+        #[cfg(feature = "mongodb")]
+        ttl_seconds: cli.ttl_seconds,
+        output_file: cli.output_file,
+        compress: cli.compress,
+        binary_output: cli.binary_output,
+        nats_url: cli.nats_url,
+        sqlite_file: cli.sqlite_file,
+        sqlite_key: cli.sqlite_key,
+        nats_subject: cli.nats_subject,
+        nats_include_private_key: cli.nats_include_private_key,
         suffix: cli.suffix,
+        progressive: cli.progressive,
+        fuzzy: cli.fuzzy.map(|target| FuzzyMatch {
+            target,
+            max_distance: cli.max_distance,
+        }),
+        min_match_len: cli.min_match_len,
+        prefix: cli.prefix,
+        smart_case: cli.smart_case,
+        match_mode: if cli.any_of {
+            MatchMode::Any
+        } else {
+            MatchMode::All
+        },
+        before_suffix: cli.before_suffix_chars.map(|allowed_chars| BeforeSuffix {
+            len: cli.before_suffix_len,
+            allowed_chars,
+        }),
+        alphabet: cli.alphabet,
+        forbidden_chars: cli.forbidden_chars,
+        required_chars: cli.required_chars,
+        min_address_len: cli.min_address_len,
+        max_address_len: cli.max_address_len,
+        max_repeat: cli.max_repeat.map(|max| max as usize),
+        min_distinct: cli.min_distinct.map(|min| min as usize),
+        min_entropy: cli.min_entropy,
+        exclude: cli.exclude,
+        log_near_misses: cli.log_near_misses.map(|short| short as usize),
+        save_timeout_secs: cli.save_timeout_secs,
+        writer_queue_size: cli.writer_queue_size as usize,
+        writer_full_policy: cli.writer_full_policy,
+        single: cli.single,
+        rate_ema_alpha: cli.rate_ema_alpha,
+        progress_every_secs: cli.progress_every_secs,
+        max_consecutive_failures: cli.max_consecutive_failures,
+        restart_delay_ms: cli.restart_delay_ms,
+        spill_on_db_failure: cli.spill_on_db_failure,
+        replay_spill: cli.replay_spill,
+        derivation_scan: cli.mnemonic.then_some(cli.derivation_scan),
+        group_by_seed: cli.group_by_seed,
+        qr: cli.qr,
+        yield_every: cli.yield_every,
+        require_on_curve: cli.require_on_curve,
+        match_ata: cli.match_ata,
+        match_private: cli.match_private,
+        summary_file: cli.summary_file,
+        count_by_pattern_file: cli.count_by_pattern_file,
+        count_by_pattern_interval_secs: cli.count_by_pattern_interval_secs,
+        max_rate: cli.max_rate,
+        max_runtime: cli.max_runtime,
+        count: cli.count,
+        #[cfg(feature = "dashboard")]
+        dashboard_port: cli.dashboard_port,
+        on_found: cli.on_found,
+        on_found_private_key: cli.on_found_private_key,
+        emit_stdout: cli.emit_stdout,
+        pubkey_only: cli.pubkey_only,
+        no_log_secrets: cli.no_log_secrets,
+        require_explicit_pattern: cli.require_explicit_pattern,
     };
 
+    if let Some(path) = &cli.config {
+        config = config.merge_file(path, |key| config::set_by_flags(key, &explicit_args))?;
+        info!("Loaded configuration from {}", path.display());
+    }
+
+    // The preset is a flag too, so it takes precedence over the config file
+    if cli.pumpfun {
+        apply_pumpfun_preset(&mut config, &explicit_args);
+    }
+
+    if config.suffix.is_empty() && config.fuzzy.is_none() && config.progressive.is_none() {
+        if config.require_explicit_pattern {
+            anyhow::bail!(
+                "No pattern given; pass --suffix or --fuzzy, or drop --require-explicit-pattern to search for '{}'",
+                DEFAULT_SUFFIX
+            );
+        }
+        config.suffix = vec![DEFAULT_SUFFIX.to_string()];
+    }
+
+    // Checked here rather than in validate_config as --yes is deliberately command-line
+    // only; ahead of the subcommands, as import and resume create the TTL index too
+    #[cfg(feature = "mongodb")]
+    if config.ttl_seconds.is_some()
+        && config.uses_mongodb()
+        && !cli.no_persist_benchmark
+        && !cli.yes
+    {
+        anyhow::bail!(
+            "--ttl-seconds makes MongoDB delete found wallets, keys included; pass --yes to confirm"
+        );
+    }
+
+    if let Some(command) = cli.command {
+        return run_command(command, &config).await;
+    }
+
+    simplify_patterns(&mut config);
+    validate_config(&config)?;
+
+    // The config file can turn these on behind clap's conflict checks
+    if wants_tui && (config.emit_stdout || config.qr || config.single) {
+        anyhow::bail!("--tui takes over the terminal, so it can't be combined with --emit-stdout, --qr or --single");
+    }
+
+    if cli.explain {
+        print_explanation(&config);
+        return Ok(());
+    }
+
+    if let Some(suffix) = &cli.simulate {
+        return simulate_find(suffix, config.alphabet);
+    }
+
+    if cli.auto_threads {
+        config.threads = auto_tune_threads(&config).await?;
+    }
+
+    if cli.estimate_only {
+        return print_estimate(&config).await;
+    }
+
+    #[cfg(feature = "mongodb")]
+    if config.collection_suffix_timestamp {
+        config.collection_name = format!(
+            "{}_{}",
+            config.collection_name,
+            chrono::Utc::now().format("%Y%m%d_%H%M%S")
+        );
+    }
+
     info!("=== Starting Solana Vanity Wallet Generator ===");
     info!("Configuration:");
-    info!("  - Looking for wallets ending with exactly 'pump' (lowercase only)");
+    match (&config.fuzzy, &config.progressive) {
+        (Some(fuzzy), _) => info!(
+            "  - Looking for wallets starting or ending within {} edits of '{}'",
+            fuzzy.max_distance, fuzzy.target
+        ),
+        (None, Some(target)) => info!(
+            "  - Looking for ever longer endings of '{}', keeping each improvement",
+            target
+        ),
+        (None, None) => info!(
+            "  - Looking for wallets ending with exactly '{}' ({})",
+            config.suffix.join("' or '"),
+            if config.smart_case {
+                "smart case"
+            } else {
+                "case-sensitive"
+            }
+        ),
+    }
+    if let Some(prefix) = &config.prefix {
+        match config.match_mode {
+            MatchMode::All => info!("  - And starting with exactly '{}'", prefix),
+            MatchMode::Any => info!("  - Or starting with exactly '{}'", prefix),
+        }
+    }
+    if let Some(before) = &config.before_suffix {
+        info!(
+            "  - Preceded by {} character(s) from: {}",
+            before.len, before.allowed_chars
+        );
+    }
+    if let Some(forbidden) = &config.forbidden_chars {
+        info!("  - Forbidden characters: {}", forbidden);
+    }
+    if let Some(required) = &config.required_chars {
+        info!("  - Allowed characters: {}", required);
+    }
+    if let Some(min) = config.min_address_len {
+        info!("  - Minimum address length: {}", min);
+    }
+    if let Some(max) = config.max_address_len {
+        info!("  - Maximum address length: {}", max);
+    }
+    if let Some(max) = config.max_repeat {
+        info!("  - At most {} repeats of a character in a row", max);
+    }
+    if let Some(min) = config.min_distinct {
+        info!("  - At least {} different characters", min);
+    }
+    if let Some(min) = config.min_entropy {
+        info!("  - At least {} bits of entropy per character", min);
+    }
+    if !config.exclude.is_empty() {
+        info!(
+            "  - Excluding addresses containing: {}",
+            config.exclude.join(", ")
+        );
+    }
+    if let Some(accounts) = config.derivation_scan {
+        info!(
+            "  - Deriving from BIP39 mnemonics, scanning {} account(s) per seed",
+            accounts
+        );
+    }
+    if !config.require_on_curve {
+        info!("  - Accepting off-curve addresses");
+    }
+    if let Some(mint) = &config.match_ata {
+        info!("  - Matching associated token accounts for mint {}", mint);
+    }
+    if config.match_private {
+        warn!("  - Matching private keys instead of addresses: this is purely cosmetic, the address stays random, and anyone who learns the pattern knows part of the secret");
+    }
     info!("  - Using {} threads", config.threads);
-    info!("  - MongoDB URI: {}", config.mongodb_uri);
-    info!("  - Database: {}", config.db_name);
-    info!("  - Collection: {}", config.collection_name);
+    if config.no_log_secrets {
+        info!("  - Private keys and mnemonics are kept out of the logs");
+    }
+    if let Some(max_rate) = config.max_rate {
+        info!("  - Rate capped at {} wallets/second", max_rate);
+    }
+    if config.writer_full_policy != FullPolicy::Block {
+        warn!(
+            "  - Writer queue of {} wallets drops finds when full ({:?}); dropped wallets are lost",
+            config.writer_queue_size, config.writer_full_policy
+        );
+    }
+    if cli.no_persist_benchmark {
+        warn!("  - Benchmark: found wallets are kept in memory only, no sink is written to");
+    }
+    #[cfg(feature = "mongodb")]
+    if config.uses_mongodb() && !cli.no_persist_benchmark {
+        info!("  - MongoDB URI: {}", config.mongodb_uri());
+        info!("  - Database: {}", config.db_name);
+        info!("  - Collection: {}", config.collection_name);
+    }
+    if let Some(path) = config.output_path() {
+        info!("  - Output file: {}", path.display());
+    }
+    if let Some(path) = &config.binary_output {
+        info!("  - Binary output file: {}", path.display());
+    }
+    if let Some(url) = &config.nats_url {
+        info!("  - NATS: {} (subject '{}')", url, config.nats_subject);
+    }
+    if let Some(path) = &config.sqlite_file {
+        info!("  - Encrypted database: {}", path.display());
+    }
     info!("=== Initialization Complete ===");
 
-    // Initialize MongoDB client
-    let db_client = MongoDBClient::new(
-        &config.mongodb_uri,
-        &config.db_name,
-        &config.collection_name,
-    )
-    .await?;
+    // Initialize the wallet sink
+    let memory = cli.no_persist_benchmark.then(InMemorySink::new);
+    let sink: Box<dyn WalletSink> = match &memory {
+        Some(memory) => Box::new(memory.clone()),
+        None => create_sink(&config).await?,
+    };
 
     // Create wallet generator
-    let wallet_generator = WalletGenerator::new(&config.suffix);
+    let wallet_generator = build_wallet_generator(&config);
 
-    // Counter for generated wallets
-    let counter = Arc::new(AtomicUsize::new(0));
-    let found_wallets = Arc::new(AtomicUsize::new(0));
+    // Counters and flags shared by every worker
+    let state = Arc::new(SharedState {
+        counter: AtomicUsize::new(0),
+        per_thread: (0..config.threads).map(|_| AtomicUsize::new(0)).collect(),
+        found_wallets: AtomicUsize::new(0),
+        found_by_pattern: wallet_generator
+            .patterns()
+            .into_iter()
+            .map(|pattern| (pattern.to_string(), AtomicUsize::new(0)))
+            .collect(),
+        rate: std::sync::Mutex::new(RateEma::new(config.rate_ema_alpha)),
+        throttle: config.max_rate.map(Throttle::new),
+        started: Instant::now(),
+        timed_progress: config.progress_every_secs.is_some(),
+        count_target: config.count,
+        rarest: std::sync::Mutex::new(None),
+        progressive_best: ProgressiveBest::default(),
+        banner,
+        #[cfg(any(feature = "dashboard", feature = "tui"))]
+        recent_finds: std::sync::Mutex::new(std::collections::VecDeque::new()),
+        shutdown: AtomicBool::new(false),
+        paused: AtomicBool::new(false),
+        stop_reason: std::sync::OnceLock::new(),
+    });
+
+    // Share the configuration between tasks
+    let config = Arc::new(config);
+
+    if config.single {
+        return run_single(&config, wallet_generator, sink, banner).await;
+    }
+
+    // Found wallets flow from the workers to a single writer task
+    let (wallet_tx, wallet_rx) = wallet_queue(config.writer_queue_size, config.writer_full_policy);
+    let writer = tokio::spawn(WalletWriter::new(config.clone(), sink)?.run(wallet_rx));
+
+    // Stop workers on Ctrl+C / SIGTERM so buffered wallets are flushed before exiting
+    let started = state.started;
+    let started_at = chrono::Utc::now();
+    {
+        let state = state.clone();
+        tokio::spawn(async move {
+            match wait_for_shutdown_signal().await {
+                Ok(signal) => {
+                    info!("Received {}, shutting down gracefully...", signal);
+                    state.request_stop(StopReason::Signal);
+                }
+                Err(e) => error!("Failed to listen for shutdown signals: {}", e),
+            }
+        });
+    }
+    #[cfg(unix)]
+    {
+        let state = state.clone();
+        tokio::spawn(async move {
+            if let Err(e) = toggle_pause_on_signal(state).await {
+                error!("Failed to listen for SIGUSR1: {}", e);
+            }
+        });
+    }
+
+    if let Some(max_runtime) = config.max_runtime {
+        let state = state.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(max_runtime).await;
+            info!(
+                "Reached --max-runtime of {}, shutting down gracefully...",
+                humantime::format_duration(max_runtime)
+            );
+            state.request_stop(StopReason::Time);
+        });
+    }
+
+    if let Some(secs) = config.progress_every_secs {
+        let state = state.clone();
+        tokio::spawn(async move {
+            let period = std::time::Duration::from_secs(secs);
+            // The first tick of a plain interval fires at once, before anything was generated
+            let mut interval =
+                tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+            loop {
+                interval.tick().await;
+                if state.shutdown.load(Ordering::Acquire) {
+                    break;
+                }
+                print_progress(None, state.counter.load(Ordering::Relaxed), &state);
+            }
+        });
+    }
+
+    if config.count_by_pattern_file.is_some() {
+        let config = config.clone();
+        let state = state.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(std::time::Duration::from_secs(
+                config.count_by_pattern_interval_secs,
+            ));
+            loop {
+                interval.tick().await;
+                state.write_pattern_counts(&config);
+            }
+        });
+    }
+
+    #[cfg(feature = "dashboard")]
+    if let Some(port) = config.dashboard_port {
+        let state = state.clone();
+        tokio::spawn(async move {
+            if let Err(e) = dashboard::serve(port, move || state.live_stats()).await {
+                error!("Dashboard stopped: {}", e);
+            }
+        });
+    }
+
+    #[cfg(feature = "tui")]
+    let tui = wants_tui.then(|| {
+        let state = state.clone();
+        tokio::task::spawn_blocking(move || {
+            logging::suspend_console(true);
+            let result = tui::run(&*state);
+            logging::suspend_console(false);
+            result
+        })
+    });
 
-    // Create a shared MongoDB client
-    let db_client = Arc::new(Mutex::new(db_client));
+    // Only counted for the monitor and the final check, which runs after every worker
+    // has been joined, so Relaxed is enough
+    let active_threads = Arc::new(AtomicUsize::new(config.threads));
 
     // Create thread pool
     let handles = (0..config.threads)
         .map(|thread_id| {
-            let wallet_generator = wallet_generator.clone();
-            let counter = counter.clone();
-            let found_wallets = found_wallets.clone();
-            let db_client = db_client.clone();
+            let config = config.clone();
+            let mut wallet_generator = wallet_generator.clone();
+            let state = state.clone();
+            let wallet_tx = wallet_tx.clone();
+            let active_threads = active_threads.clone();
 
             tokio::spawn(async move {
                 info!("Starting thread {}", thread_id);
+                let mut restarts = WorkerRestarts::new(&config);
 
                 // Main processing loop with error recovery
                 loop {
                     // Try to run the wallet generation loop
                     // If it fails, log the error and restart the thread
-                    if let Err(e) = wallet_generation_loop(
+                    let run_started = Instant::now();
+                    match wallet_generation_loop(
                         thread_id,
-                        &wallet_generator,
-                        &counter,
-                        &found_wallets,
-                        &db_client,
+                        &config,
+                        &mut wallet_generator,
+                        &state,
+                        &wallet_tx,
                     )
                     .await
                     {
-                        error!(
-                            "Thread {} encountered an error: {}. Restarting thread...",
-                            thread_id, e
-                        );
-                        // Sleep briefly before restarting to prevent rapid restart loops
-                        tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-                        warn!("Restarting thread {}", thread_id);
+                        Ok(()) => break,
+                        Err(e) => {
+                            let Some(delay) = restarts.after_failure(run_started.elapsed())
+                            else {
+                                error!(
+                                    "Thread {} encountered an error: {}. Giving up after {} consecutive failures",
+                                    thread_id, e, restarts.consecutive_failures
+                                );
+                                active_threads.fetch_sub(1, Ordering::Relaxed);
+                                break;
+                            };
+                            error!(
+                                "Thread {} encountered an error: {}. Restarting in {:?}...",
+                                thread_id, e, delay
+                            );
+                            // Back off so a persistent error doesn't turn into a tight restart loop
+                            tokio::time::sleep(delay).await;
+                            warn!("Restarting thread {}", thread_id);
+                        }
                     }
                 }
             })
@@ -221,18 +2543,30 @@ async fn main() -> Result<()> {
         .collect::<Vec<_>>();
 
     // Create a thread monitoring task
-    let active_threads = Arc::new(AtomicUsize::new(config.threads));
     let active_threads_clone = active_threads.clone();
+    let monitor_config = config.clone();
+    let monitor_state = state.clone();
 
     // Spawn a monitoring task
     tokio::spawn(async move {
+        let config = monitor_config;
+        let mut last_counts = vec![0; config.threads];
+        let mut last_check = Instant::now();
         loop {
             tokio::time::sleep(tokio::time::Duration::from_secs(60)).await;
-            let current_active = active_threads_clone.load(Ordering::SeqCst);
+            let current_active = active_threads_clone.load(Ordering::Relaxed);
             info!(
-                "Thread monitor: {} of {} threads active",
-                current_active, config.threads
+                "Thread monitor: {} of {} threads active{}",
+                current_active,
+                config.threads,
+                if monitor_state.paused.load(Ordering::Relaxed) {
+                    " (PAUSED)"
+                } else {
+                    ""
+                }
             );
+            log_thread_rates(&monitor_state, &mut last_counts, last_check.elapsed());
+            last_check = Instant::now();
 
             if current_active < config.threads {
                 warn!(
@@ -243,13 +2577,157 @@ async fn main() -> Result<()> {
         }
     });
 
-    // Wait for all threads to complete (they won't unless interrupted)
+    // Wait for all threads to complete (they won't unless shut down)
     for handle in handles {
         if let Err(e) = handle.await {
             error!("A thread has terminated with error: {}", e);
-            active_threads.fetch_sub(1, Ordering::SeqCst);
+            active_threads.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+
+    // Workers have dropped their senders; wait for the writer to drain the queue
+    let dropped = wallet_tx.dropped();
+    drop(wallet_tx);
+    if let Err(e) = writer.await {
+        error!("Writer task terminated with error: {}", e);
+    }
+
+    #[cfg(feature = "tui")]
+    if let Some(tui) = tui {
+        // Closes the TUI if the run ended on its own, e.g. on --count
+        state.shutdown.store(true, Ordering::Release);
+        match tui.await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => error!("TUI stopped: {}", e),
+            Err(e) => error!("TUI task terminated with error: {}", e),
         }
     }
 
+    state.write_pattern_counts(&config);
+    let mut summary = summarize_run(started, started_at, &config, &state);
+    if let Some(memory) = &memory {
+        summary.collection_name = None;
+        summary.kept_in_memory = Some(memory.len());
+    }
+    if config.writer_full_policy != FullPolicy::Block {
+        summary.dropped_by_writer_queue = Some(dropped);
+    }
+    log_summary(&summary, &state);
+    match summary.write(&config.summary_file) {
+        Ok(()) => info!("Run summary written to {}", config.summary_file.display()),
+        Err(e) => error!(
+            "Failed to write run summary to {}: {}",
+            config.summary_file.display(),
+            e
+        ),
+    }
+
+    if active_threads.load(Ordering::Relaxed) == 0 {
+        anyhow::bail!("All worker threads stopped because of errors");
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn workers_give_up_on_a_sink_that_always_fails() {
+        // Stands in for a worker whose every save fails straight away
+        let run_against_dead_sink = || -> Result<()> { anyhow::bail!("sink is down") };
+        let mut restarts = WorkerRestarts {
+            max_consecutive_failures: 3,
+            restart_delay_ms: 0,
+            consecutive_failures: 0,
+        };
+
+        let mut runs = 0;
+        while let Err(e) = run_against_dead_sink() {
+            runs += 1;
+            assert_eq!(e.to_string(), "sink is down");
+            if restarts.after_failure(Duration::ZERO).is_none() {
+                break;
+            }
+        }
+        // The first run plus one restart per allowed failure
+        assert_eq!(runs, 4);
+        assert_eq!(restarts.consecutive_failures, 4);
+    }
+
+    #[test]
+    fn long_runs_reset_the_failure_count() {
+        let mut restarts = WorkerRestarts {
+            max_consecutive_failures: 1,
+            restart_delay_ms: 100,
+            consecutive_failures: 0,
+        };
+        assert_eq!(
+            restarts.after_failure(Duration::ZERO),
+            Some(Duration::from_millis(100))
+        );
+        assert_eq!(restarts.after_failure(Duration::ZERO), None);
+        assert!(restarts.after_failure(FAILURE_RESET_AFTER).is_some());
+    }
+
+    #[test]
+    fn restart_delay_doubles_per_failure_up_to_the_cap() {
+        // --restart-delay-ms 0 restarts straight away however often a worker fails
+        for failures in [1, 2, 10, u32::MAX] {
+            assert_eq!(restart_delay(0, failures), Duration::ZERO);
+        }
+
+        let delays: Vec<u64> = (1..=5)
+            .map(|failures| restart_delay(100, failures).as_millis() as u64)
+            .collect();
+        assert_eq!(delays, [100, 200, 400, 800, 1600]);
+
+        assert_eq!(restart_delay(100, 20), MAX_RESTART_DELAY);
+        assert_eq!(restart_delay(100, u32::MAX), MAX_RESTART_DELAY);
+        assert_eq!(restart_delay(u64::MAX, 1), MAX_RESTART_DELAY);
+    }
+
+    #[test]
+    fn progressive_keeps_only_longer_matches_of_the_target() {
+        use solana_sdk::signature::{Keypair, Signer};
+        use solana_vanity_wallet::wallet_generator::SequenceKeypairs;
+
+        let longest = Keypair::new();
+        let address = longest.pubkey().to_string();
+        let target = &address[address.len() - 3..];
+        let ending = |len: usize| {
+            std::iter::repeat_with(Keypair::new)
+                .find(|keypair| {
+                    let address = keypair.pubkey().to_string();
+                    address.ends_with(&target[3 - len..]) && !address.ends_with(&target[2 - len..])
+                })
+                .unwrap()
+        };
+        let (one, two) = (ending(1), ending(2));
+        let copy = |keypair: &Keypair| Keypair::from_bytes(&keypair.to_bytes()).unwrap();
+        let keypairs = vec![
+            copy(&one),
+            copy(&one),
+            copy(&two),
+            copy(&one),
+            longest,
+            copy(&two),
+        ];
+
+        let mut generator = WalletGenerator::new(MatchConfig::new(&matcher::target_tails(target)))
+            .with_keypair_source(Box::new(SequenceKeypairs::new(keypairs)));
+        let best = ProgressiveBest::default();
+        let mut kept = Vec::new();
+        for _ in 0..6 {
+            let hit = generator
+                .try_one()
+                .expect("every key ends with the target's last character");
+            if best.record_improvement(hit.found.len) {
+                kept.push(hit.found.len);
+            }
+        }
+        assert_eq!(kept, [1, 2, 3]);
+        assert!(!best.record_improvement(3));
+    }
+}
@@ -0,0 +1,1083 @@
+use crate::error::{Error, Result};
+use serde::{Deserialize, Serialize};
+
+/// Base58 alphabets used by different chains
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Base58Alphabet {
+    /// The Bitcoin alphabet, also used by Solana
+    #[default]
+    Bitcoin,
+    /// The Ripple (XRP) alphabet
+    Ripple,
+    /// The Flickr alphabet
+    Flickr,
+}
+
+impl Base58Alphabet {
+    /// The 58 characters of this alphabet, in encoding order
+    pub fn chars(self) -> &'static str {
+        match self {
+            Self::Bitcoin => "123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz",
+            Self::Ripple => "rpshnaf39wBUDNEGHJKLM4PQRST7VWXYZ2bcdeCg65jkm8oFqi1tuvAxyz",
+            Self::Flickr => "123456789abcdefghijkmnopqrstuvwxyzABCDEFGHJKLMNPQRSTUVWXYZ",
+        }
+    }
+}
+
+/// Make sure a pattern only uses characters that can appear in an address,
+/// otherwise the search would run forever without a match. Empty patterns and ones
+/// with whitespace are refused too; they almost always come from a shell-quoting mistake.
+pub fn validate_pattern(pattern: &str, alphabet: Base58Alphabet) -> Result<()> {
+    if pattern.is_empty() {
+        return Err(Error::EmptyPattern);
+    }
+    if pattern.chars().any(char::is_whitespace) {
+        return Err(Error::WhitespaceInPattern {
+            pattern: pattern.to_string(),
+        });
+    }
+    if let Some(character) = pattern.chars().find(|&c| !alphabet.chars().contains(c)) {
+        return Err(Error::InvalidPattern {
+            pattern: pattern.to_string(),
+            character,
+            alphabet,
+        });
+    }
+
+    Ok(())
+}
+
+/// Whether `address` ends with `suffix`, compared as plain bytes. Base58 is ASCII, so
+/// this is the same as a string comparison; it's what the suffix check runs on every key.
+pub fn matches_suffix_bytes(address: &[u8], suffix: &[u8]) -> bool {
+    address.len() >= suffix.len() && address[address.len() - suffix.len()..] == *suffix
+}
+
+/// Every ending of `target`, longest first: "fun", "un", "n". Searching for all of them
+/// finds ever longer matches of the target as a run goes on.
+pub fn target_tails(target: &str) -> Vec<String> {
+    // Base58 is ASCII, so byte offsets are character offsets
+    (0..target.len())
+        .map(|start| target[start..].to_string())
+        .collect()
+}
+
+/// Remove repeated patterns and return the ones removed, each paired with the
+/// pattern that covers it. A repeat can never match on its own, but it would get
+/// its own (always empty) entry in the per-pattern counts. Of exact repeats the
+/// first is kept. With `smart_case` a lowercase pattern matches every case, so it
+/// removes its case variants wherever they are and stays at its own position, even
+/// when a variant came first: `["Pump", "moon", "pump"]` becomes `["moon", "pump"]`.
+///
+/// ```
+/// use solana_vanity_wallet::matcher::dedup_patterns;
+///
+/// let patterns = |p: &[&str]| p.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+/// let mut suffixes = patterns(&["pump", "moon", "pump"]);
+/// let removed = dedup_patterns(&mut suffixes, false);
+/// assert_eq!(removed, [("pump".to_string(), "pump".to_string())]);
+/// assert_eq!(suffixes, ["pump", "moon"]);
+///
+/// let mut suffixes = patterns(&["ab", "AB"]);
+/// assert!(dedup_patterns(&mut suffixes, false).is_empty());
+///
+/// let mut suffixes = patterns(&["Pump", "PUMP", "pump"]);
+/// assert_eq!(dedup_patterns(&mut suffixes, true).len(), 2);
+/// assert_eq!(suffixes, ["pump"]);
+///
+/// let mut suffixes = patterns(&["Pump", "moon", "pump"]);
+/// assert_eq!(dedup_patterns(&mut suffixes, true), [("Pump".to_string(), "pump".to_string())]);
+/// assert_eq!(suffixes, ["moon", "pump"]);
+/// ```
+pub fn dedup_patterns(patterns: &mut Vec<String>, smart_case: bool) -> Vec<(String, String)> {
+    let covers = |kept: &str, pattern: &str| {
+        kept == pattern || (ignores_case(kept, smart_case) && kept.eq_ignore_ascii_case(pattern))
+    };
+    let mut kept: Vec<String> = Vec::new();
+    let mut removed = Vec::new();
+    for pattern in patterns.drain(..) {
+        if let Some(cover) = kept.iter().find(|kept| covers(kept, &pattern)) {
+            removed.push((pattern, cover.clone()));
+            continue;
+        }
+        kept.retain(|variant| {
+            let covered = covers(&pattern, variant);
+            if covered {
+                removed.push((variant.clone(), pattern.clone()));
+            }
+            !covered
+        });
+        kept.push(pattern);
+    }
+    *patterns = kept;
+    removed
+}
+
+/// Whether smart case lets `pattern` match in any case: it has no uppercase letter
+fn ignores_case(pattern: &str, smart_case: bool) -> bool {
+    smart_case && !pattern.bytes().any(|b| b.is_ascii_uppercase())
+}
+
+/// Chance that `len` given base58 characters appear at a fixed position, 58^-len
+fn pattern_probability(len: usize) -> f64 {
+    58f64.powi(-(len as i32))
+}
+
+/// Levenshtein distance between two byte strings (base58 is plain ASCII)
+pub fn edit_distance(a: &[u8], b: &[u8]) -> usize {
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+
+    for (i, &ca) in a.iter().enumerate() {
+        current[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+
+    previous[b.len()]
+}
+
+/// Approximate pattern: the start or end of the address must be within
+/// `max_distance` edits of `target`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FuzzyMatch {
+    pub target: String,
+    pub max_distance: usize,
+}
+
+impl FuzzyMatch {
+    fn matches(&self, pubkey: &str) -> bool {
+        let pubkey = pubkey.as_bytes();
+        let target = self.target.as_bytes();
+
+        // Insertions and deletions shift where the match ends, so compare every
+        // window length they allow, but never more than that
+        let min_len = target.len().saturating_sub(self.max_distance);
+        let max_len = (target.len() + self.max_distance).min(pubkey.len());
+
+        (min_len..=max_len).any(|len| {
+            edit_distance(&pubkey[..len], target) <= self.max_distance
+                || edit_distance(&pubkey[pubkey.len() - len..], target) <= self.max_distance
+        })
+    }
+
+    /// Smallest edit distance between `target` and the start or end of the address
+    fn best_distance(&self, pubkey: &str) -> usize {
+        let pubkey = pubkey.as_bytes();
+        let target = self.target.as_bytes();
+        let min_len = target.len().saturating_sub(self.max_distance);
+        let max_len = (target.len() + self.max_distance).min(pubkey.len());
+
+        (min_len..=max_len)
+            .flat_map(|len| {
+                [
+                    edit_distance(&pubkey[..len], target),
+                    edit_distance(&pubkey[pubkey.len() - len..], target),
+                ]
+            })
+            .min()
+            .unwrap_or(target.len())
+    }
+}
+
+/// Characters required directly before the suffix, e.g. two digits before "pump"
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BeforeSuffix {
+    /// How many characters before the suffix are checked
+    pub len: usize,
+    /// Each of those characters must be one of these
+    pub allowed_chars: String,
+}
+
+impl BeforeSuffix {
+    fn matches(&self, segment: &str) -> bool {
+        segment.chars().all(|c| self.allowed_chars.contains(c))
+    }
+}
+
+/// How the prefix and suffix conditions combine when a prefix is configured
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchMode {
+    /// The address must satisfy both
+    #[default]
+    All,
+    /// Satisfying either one counts as a match
+    Any,
+}
+
+/// Which string of a keypair the pattern is matched against
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchTarget {
+    /// The base58 public key, i.e. the wallet address
+    #[default]
+    PublicKey,
+    /// The base58 private key, for a memorable secret. Purely cosmetic: it has no
+    /// meaning on-chain, and whoever learns the pattern knows part of the secret.
+    PrivateKey,
+}
+
+/// Which of the configured conditions a matching address satisfied
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchedCondition {
+    Prefix,
+    Suffix,
+    Both,
+}
+
+/// A successful match: the pattern the wallet is labelled with and the condition that held
+#[derive(Clone, Copy, Debug)]
+pub struct PatternMatch<'a> {
+    pub pattern: &'a str,
+    pub condition: MatchedCondition,
+    /// Address characters the match pinned down: prefix plus suffix, including any
+    /// before-suffix characters, or the fuzzy target less its edits. Longer is rarer.
+    pub len: usize,
+}
+
+/// How likely a single configured pattern is to match a random address
+#[derive(Clone, Debug)]
+pub struct PatternEstimate {
+    /// The pattern as shown to users, e.g. "pump" or "Sol…pump" for a prefix and suffix
+    pub pattern: String,
+    pub condition: MatchedCondition,
+    /// Address characters the pattern pins down
+    pub len: usize,
+    /// Chance that a random address matches it
+    pub probability: f64,
+}
+
+/// Heuristic "how good does it look" score of a matched address, for picking the best
+/// of many finds later. Only computed for matches. The rules:
+///
+/// - 10 points per address character the match pinned down ([`PatternMatch::len`])
+/// - 5 extra points when the prefix matched, as the start is what people see first
+/// - 3 points per character that repeats the one before it, e.g. "...ppump" or "...1111"
+pub fn vanity_score(pubkey: &str, found: &PatternMatch) -> u32 {
+    let matched = 10 * found.len as u32;
+    let position = match found.condition {
+        MatchedCondition::Prefix | MatchedCondition::Both => 5,
+        MatchedCondition::Suffix => 0,
+    };
+    let repeats = pubkey
+        .as_bytes()
+        .windows(2)
+        .filter(|pair| pair[0] == pair[1])
+        .count() as u32;
+
+    matched + position + 3 * repeats
+}
+
+/// Whether `text` contains `needle`, ignoring ASCII case
+pub fn contains_ignore_case(text: &str, needle: &str) -> bool {
+    needle.is_empty()
+        || text
+            .as_bytes()
+            .windows(needle.len())
+            .any(|window| window.eq_ignore_ascii_case(needle.as_bytes()))
+}
+
+/// Length of the longest run of one repeated character, e.g. 3 for "a111b"
+pub fn longest_run(text: &str) -> usize {
+    text.as_bytes()
+        .chunk_by(|a, b| a == b)
+        .map(<[u8]>::len)
+        .max()
+        .unwrap_or(0)
+}
+
+/// Number of different characters in an ASCII string
+fn distinct_chars(text: &str) -> usize {
+    let mut seen = [false; 128];
+    text.bytes()
+        .filter(|&byte| !std::mem::replace(&mut seen[usize::from(byte & 0x7f)], true))
+        .count()
+}
+
+/// Shannon entropy of the character distribution of an ASCII string, in bits per
+/// character: 0 for "1111", 2 for "abcd". A 44-character string tops out at log2(44),
+/// about 5.46; random addresses average around 4.8.
+pub fn address_entropy(text: &str) -> f64 {
+    let mut counts = [0usize; 128];
+    for byte in text.bytes() {
+        counts[usize::from(byte & 0x7f)] += 1;
+    }
+    let len = text.len() as f64;
+    -counts
+        .iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| {
+            let p = count as f64 / len;
+            p * p.log2()
+        })
+        .sum::<f64>()
+}
+
+/// Rules a public key must satisfy to count as a vanity wallet
+#[derive(Clone, Debug)]
+pub struct MatchConfig {
+    /// Exact (case-sensitive unless `smart_case`) suffixes; the address must end with one of them
+    pub suffixes: Vec<String>,
+    /// If set, replaces the exact suffix check with an edit-distance match
+    pub fuzzy: Option<FuzzyMatch>,
+    /// Reject matches covering fewer address characters than this, e.g. fuzzy
+    /// matches that are mostly edits
+    pub min_match_len: Option<usize>,
+    /// If set, the characters just before a matched suffix must also match
+    pub before_suffix: Option<BeforeSuffix>,
+    /// Exact (case-sensitive unless `smart_case`) prefix the address must start with
+    pub prefix: Option<String>,
+    /// Like ripgrep's smart case: a prefix or suffix without uppercase letters
+    /// matches in any case, one with uppercase letters matches exactly
+    pub smart_case: bool,
+    /// Whether both the prefix and the suffix must match, or either one
+    pub mode: MatchMode,
+    /// Characters that must not appear anywhere in the address
+    pub forbidden_chars: Option<String>,
+    /// If set, every character of the address must be one of these
+    pub required_chars: Option<String>,
+    /// Minimum length of the base58 address
+    pub min_address_len: Option<usize>,
+    /// Maximum length of the base58 address
+    pub max_address_len: Option<usize>,
+    /// Reject addresses with a run of one character longer than this
+    pub max_repeat: Option<usize>,
+    /// Reject addresses with fewer different characters than this
+    pub min_distinct: Option<usize>,
+    /// Reject addresses whose [`address_entropy`] is below this many bits per character
+    pub min_entropy: Option<f64>,
+    /// Reject addresses containing any of these substrings, compared in any case
+    pub exclude: Vec<String>,
+    /// Whether the pattern and filters apply to the public or the private key
+    pub target: MatchTarget,
+}
+
+impl MatchConfig {
+    pub fn new(suffixes: &[String]) -> Self {
+        Self {
+            suffixes: suffixes.to_vec(),
+            fuzzy: None,
+            min_match_len: None,
+            before_suffix: None,
+            prefix: None,
+            smart_case: false,
+            mode: MatchMode::All,
+            forbidden_chars: None,
+            required_chars: None,
+            min_address_len: None,
+            max_address_len: None,
+            max_repeat: None,
+            min_distinct: None,
+            min_entropy: None,
+            exclude: Vec::new(),
+            target: MatchTarget::PublicKey,
+        }
+    }
+
+    /// Every pattern wallets can be labelled with, in the order they were given.
+    /// The prefix only labels wallets in `Any` mode; otherwise every match has a suffix.
+    pub fn patterns(&self) -> Vec<&str> {
+        let mut patterns = Vec::new();
+        if let (Some(prefix), MatchMode::Any) = (&self.prefix, self.mode) {
+            patterns.push(prefix.as_str());
+        }
+        match &self.fuzzy {
+            Some(fuzzy) => patterns.push(fuzzy.target.as_str()),
+            None => patterns.extend(self.suffixes.iter().map(String::as_str)),
+        }
+        patterns
+    }
+
+    /// Roughly how many keys have to be generated per match, ignoring the length and
+    /// character filters. The last characters of an address are close to uniform, so a
+    /// suffix of length n matches about once in 58^n keys; prefixes are treated the same,
+    /// although leading characters are less uniform. `None` for fuzzy matching.
+    pub fn estimated_attempts(&self) -> Option<f64> {
+        if self.fuzzy.is_some() || self.suffixes.is_empty() {
+            return None;
+        }
+        let probability: f64 = self
+            .suffixes
+            .iter()
+            .map(|suffix| self.suffix_probability(suffix))
+            .sum();
+        let probability = match &self.prefix {
+            Some(prefix) => {
+                let prefix_probability = self.affix_probability(prefix);
+                match self.mode {
+                    MatchMode::All => probability * prefix_probability,
+                    MatchMode::Any => {
+                        probability + prefix_probability - probability * prefix_probability
+                    }
+                }
+            }
+            None => probability,
+        };
+        Some(1.0 / probability)
+    }
+
+    /// The odds of each pattern on its own, using the same model as
+    /// [`estimated_attempts`](Self::estimated_attempts). `None` for fuzzy matching.
+    pub fn pattern_estimates(&self) -> Option<Vec<PatternEstimate>> {
+        if self.fuzzy.is_some() {
+            return None;
+        }
+        let before_len = self.before_suffix.as_ref().map_or(0, |before| before.len);
+        let mut estimates = Vec::new();
+
+        if let (Some(prefix), MatchMode::Any) = (&self.prefix, self.mode) {
+            estimates.push(PatternEstimate {
+                pattern: prefix.clone(),
+                condition: MatchedCondition::Prefix,
+                len: prefix.len(),
+                probability: self.affix_probability(prefix),
+            });
+        }
+        for suffix in &self.suffixes {
+            let probability = self.suffix_probability(suffix);
+            estimates.push(match (&self.prefix, self.mode) {
+                (Some(prefix), MatchMode::All) => PatternEstimate {
+                    pattern: format!("{}…{}", prefix, suffix),
+                    condition: MatchedCondition::Both,
+                    len: prefix.len() + suffix.len() + before_len,
+                    probability: probability * self.affix_probability(prefix),
+                },
+                _ => PatternEstimate {
+                    pattern: suffix.clone(),
+                    condition: MatchedCondition::Suffix,
+                    len: suffix.len() + before_len,
+                    probability,
+                },
+            });
+        }
+        Some(estimates)
+    }
+
+    /// Chance that a random address ends with `suffix`, including the before-suffix rule
+    fn suffix_probability(&self, suffix: &str) -> f64 {
+        let before_suffix_probability = self.before_suffix.as_ref().map_or(1.0, |before| {
+            (before.allowed_chars.chars().count() as f64 / 58.0).powi(before.len as i32)
+        });
+        self.affix_probability(suffix) * before_suffix_probability
+    }
+
+    /// Chance that a random address starts or ends with `pattern`. A pattern matched in
+    /// any case has two spellings for each letter whose other case is in the alphabet.
+    fn affix_probability(&self, pattern: &str) -> f64 {
+        if !self.ignores_case(pattern) {
+            return pattern_probability(pattern.len());
+        }
+        let alphabet = Base58Alphabet::Bitcoin.chars();
+        pattern
+            .chars()
+            .map(|c| {
+                let upper = c.to_ascii_uppercase();
+                let variants = 1 + usize::from(upper != c && alphabet.contains(upper));
+                variants as f64 / 58.0
+            })
+            .product()
+    }
+
+    /// Suffixes made redundant by a shorter or equally long one, paired with it: every
+    /// address ending with the first also ends with the second, so the first doesn't
+    /// find anything more and only splits the per-pattern counts. With `--before-suffix`
+    /// the characters before each suffix differ, so nothing is redundant.
+    ///
+    /// ```
+    /// use solana_vanity_wallet::matcher::{BeforeSuffix, MatchConfig};
+    ///
+    /// let suffixes = |s: &[&str]| s.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+    /// let config = MatchConfig::new(&suffixes(&["ab", "cab", "xyz"]));
+    /// assert_eq!(config.subsumed_suffixes(), [("cab", "ab")]);
+    ///
+    /// // Case matters unless smart case makes the shorter suffix match any case
+    /// let config = MatchConfig::new(&suffixes(&["ab", "CAB"]));
+    /// assert!(config.subsumed_suffixes().is_empty());
+    /// let config = MatchConfig { smart_case: true, ..config };
+    /// assert_eq!(config.subsumed_suffixes(), [("CAB", "ab")]);
+    ///
+    /// // "cab" matches "CAB" too, which the exact "AB" doesn't cover
+    /// let config = MatchConfig::new(&suffixes(&["AB", "cab"]));
+    /// let config = MatchConfig { smart_case: true, ..config };
+    /// assert!(config.subsumed_suffixes().is_empty());
+    ///
+    /// // A lowercase suffix covers its own case variants under smart case
+    /// let config = MatchConfig::new(&suffixes(&["Pump", "pump"]));
+    /// assert!(config.subsumed_suffixes().is_empty());
+    /// let config = MatchConfig { smart_case: true, ..config };
+    /// assert_eq!(config.subsumed_suffixes(), [("Pump", "pump")]);
+    ///
+    /// let config = MatchConfig {
+    ///     before_suffix: Some(BeforeSuffix { len: 1, allowed_chars: "123".to_string() }),
+    ///     ..MatchConfig::new(&suffixes(&["ab", "cab"]))
+    /// };
+    /// assert!(config.subsumed_suffixes().is_empty());
+    /// ```
+    pub fn subsumed_suffixes(&self) -> Vec<(&str, &str)> {
+        if self.fuzzy.is_some() || self.before_suffix.is_some() {
+            return Vec::new();
+        }
+        self.suffixes
+            .iter()
+            .filter_map(|narrow| {
+                self.suffixes
+                    .iter()
+                    // Identical suffixes are repeats, which `dedup_patterns` removes
+                    .filter(|broad| broad.len() <= narrow.len() && broad != &narrow)
+                    .find(|broad| self.suffix_covers(broad, narrow))
+                    .map(|broad| (narrow.as_str(), broad.as_str()))
+            })
+            .collect()
+    }
+
+    /// Whether every address ending with `narrow` also ends with `broad`
+    fn suffix_covers(&self, broad: &str, narrow: &str) -> bool {
+        let tail = &narrow[narrow.len().saturating_sub(broad.len())..];
+        if self.ignores_case(broad) {
+            tail.eq_ignore_ascii_case(broad)
+        } else {
+            // A caseless `narrow` also matches endings in other cases than its own
+            let tail_any_case =
+                self.ignores_case(narrow) && tail.bytes().any(|b| b.is_ascii_alphabetic());
+            !tail_any_case && tail == broad
+        }
+    }
+
+    /// Whether smart case lets `pattern` match in any case
+    fn ignores_case(&self, pattern: &str) -> bool {
+        ignores_case(pattern, self.smart_case)
+    }
+
+    fn prefix_matches(&self, pubkey: &str, prefix: &str) -> bool {
+        if self.ignores_case(prefix) {
+            pubkey
+                .get(..prefix.len())
+                .is_some_and(|head| head.eq_ignore_ascii_case(prefix))
+        } else {
+            pubkey.starts_with(prefix)
+        }
+    }
+
+    /// Check whether a base58 public key satisfies the pattern and all filters
+    ///
+    /// ```
+    /// use solana_vanity_wallet::matcher::MatchConfig;
+    ///
+    /// let mut config = MatchConfig::new(&["pump".to_string()]);
+    /// config.exclude = vec!["fck".to_string()];
+    /// assert!(config.matches("7xKXtg2CW87d97TXJSDpbD5jBkheTqA83TZRuJosgpump"));
+    /// assert!(!config.matches("7xKXtg2CW87d97TXJSDpbD5jBkheTqA83TZRuJoFcKpump"));
+    /// ```
+    pub fn matches(&self, pubkey: &str) -> bool {
+        self.matched_pattern(pubkey).is_some()
+    }
+
+    /// The pattern a public key matched, if it matched and passes all filters
+    pub fn matched_pattern(&self, pubkey: &str) -> Option<&str> {
+        self.find_match(pubkey).map(|found| found.pattern)
+    }
+
+    /// Match a public key against the prefix, suffix and filters. A wallet is labelled
+    /// with its suffix whenever one matched; when several did, the longest (rarest) wins.
+    pub fn find_match(&self, pubkey: &str) -> Option<PatternMatch<'_>> {
+        let suffix = self.matched_suffix(pubkey);
+        let prefix = self
+            .prefix
+            .as_deref()
+            .filter(|prefix| self.prefix_matches(pubkey, prefix));
+
+        let (pattern, condition) = match (&self.prefix, self.mode, prefix, suffix) {
+            (None, _, _, Some(suffix)) => (suffix, MatchedCondition::Suffix),
+            (Some(_), _, Some(_), Some(suffix)) => (suffix, MatchedCondition::Both),
+            (Some(_), MatchMode::Any, Some(prefix), None) => (prefix, MatchedCondition::Prefix),
+            (Some(_), MatchMode::Any, None, Some(suffix)) => (suffix, MatchedCondition::Suffix),
+            _ => return None,
+        };
+
+        if !self.passes_filters(pubkey) {
+            return None;
+        }
+
+        // Only computed for matches, so it costs nothing in the search loop
+        let suffix_len = match (&self.fuzzy, suffix) {
+            (Some(fuzzy), Some(_)) => fuzzy
+                .target
+                .len()
+                .saturating_sub(fuzzy.best_distance(pubkey)),
+            (None, Some(suffix)) => {
+                suffix.len() + self.before_suffix.as_ref().map_or(0, |before| before.len)
+            }
+            (_, None) => 0,
+        };
+        let len = suffix_len + prefix.map_or(0, str::len);
+        if self.min_match_len.is_some_and(|min| len < min) {
+            return None;
+        }
+
+        Some(PatternMatch {
+            pattern,
+            condition,
+            len,
+        })
+    }
+
+    /// For an address that didn't match, the suffix it ends with all but the first
+    /// few characters of, and how many characters it was short by (at most `max_short`).
+    /// Only the suffixes are compared; fuzzy matching has no near misses.
+    pub fn near_miss(&self, pubkey: &str, max_short: usize) -> Option<(&str, usize)> {
+        if self.fuzzy.is_some() {
+            return None;
+        }
+        // Closest first, so a key one short of one suffix isn't reported as two short of another
+        (1..=max_short).find_map(|short| {
+            self.suffixes
+                .iter()
+                .filter(|suffix| short < suffix.len())
+                .find(|suffix| {
+                    let tail = &suffix[short..];
+                    let end = &pubkey[pubkey.len().saturating_sub(tail.len())..];
+                    if self.ignores_case(suffix) {
+                        end.eq_ignore_ascii_case(tail)
+                    } else {
+                        end == tail
+                    }
+                })
+                .map(|suffix| (suffix.as_str(), short))
+        })
+    }
+
+    /// The suffix (or fuzzy target) the address ends with, if any
+    fn matched_suffix(&self, pubkey: &str) -> Option<&str> {
+        match &self.fuzzy {
+            Some(fuzzy) => fuzzy.matches(pubkey).then_some(fuzzy.target.as_str()),
+            None => self
+                .suffixes
+                .iter()
+                .filter(|suffix| self.suffix_matches(pubkey, suffix))
+                .max_by_key(|suffix| suffix.len())
+                .map(String::as_str),
+        }
+    }
+
+    fn suffix_matches(&self, pubkey: &str, suffix: &str) -> bool {
+        let Some(rest_len) = pubkey.len().checked_sub(suffix.len()) else {
+            return false;
+        };
+        let matched = if self.ignores_case(suffix) {
+            pubkey.as_bytes()[rest_len..].eq_ignore_ascii_case(suffix.as_bytes())
+        } else {
+            matches_suffix_bytes(pubkey.as_bytes(), suffix.as_bytes())
+        };
+        if !matched {
+            return false;
+        }
+        let rest = &pubkey[..rest_len];
+        match &self.before_suffix {
+            // Base58 is ASCII, so byte offsets are character offsets
+            Some(before) => {
+                rest.len() >= before.len && before.matches(&rest[rest.len() - before.len..])
+            }
+            None => true,
+        }
+    }
+
+    fn passes_filters(&self, pubkey: &str) -> bool {
+        // Base58 addresses are normally 32-44 characters, shorter with leading zero bytes
+        if self.min_address_len.is_some_and(|min| pubkey.len() < min)
+            || self.max_address_len.is_some_and(|max| pubkey.len() > max)
+        {
+            return false;
+        }
+
+        if let Some(forbidden) = &self.forbidden_chars {
+            if pubkey.chars().any(|c| forbidden.contains(c)) {
+                return false;
+            }
+        }
+
+        if let Some(required) = &self.required_chars {
+            if !pubkey.chars().all(|c| required.contains(c)) {
+                return false;
+            }
+        }
+
+        if self
+            .exclude
+            .iter()
+            .any(|excluded| contains_ignore_case(pubkey, excluded))
+        {
+            return false;
+        }
+
+        if self.max_repeat.is_some_and(|max| longest_run(pubkey) > max)
+            || self
+                .min_distinct
+                .is_some_and(|min| distinct_chars(pubkey) < min)
+            || self
+                .min_entropy
+                .is_some_and(|min| address_entropy(pubkey) < min)
+        {
+            return false;
+        }
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 40-character address body; with a four-character pattern it is 44 long
+    const BODY: &str = "7xKXtg2CW87d97TXJSDpbD5jBkheTqA83TZRuJos";
+
+    fn suffixes(patterns: &[&str]) -> Vec<String> {
+        patterns.iter().map(|p| p.to_string()).collect()
+    }
+
+    #[test]
+    fn validate_pattern_rejects_characters_outside_base58() {
+        for pattern in ["0", "O", "I", "l", "pu0p", "Oak", "Ice", "lol"] {
+            let err = validate_pattern(pattern, Base58Alphabet::Bitcoin).unwrap_err();
+            assert!(
+                matches!(err, Error::InvalidPattern { .. }),
+                "{pattern}: {err}"
+            );
+        }
+        validate_pattern("pump", Base58Alphabet::Bitcoin).unwrap();
+    }
+
+    #[test]
+    fn target_tails_lists_every_ending_longest_first() {
+        assert_eq!(target_tails("fun"), suffixes(&["fun", "un", "n"]));
+        assert_eq!(target_tails("a"), suffixes(&["a"]));
+        assert!(target_tails("").is_empty());
+    }
+
+    #[test]
+    fn validate_pattern_rejects_empty_and_whitespace_patterns() {
+        let err = validate_pattern("", Base58Alphabet::Bitcoin).unwrap_err();
+        assert!(matches!(err, Error::EmptyPattern), "{err}");
+
+        for pattern in [" ", "\t", "pu mp", " pump", "pump\n"] {
+            let err = validate_pattern(pattern, Base58Alphabet::Bitcoin).unwrap_err();
+            assert!(
+                matches!(&err, Error::WhitespaceInPattern { pattern: p } if p == pattern),
+                "{pattern:?}: {err}"
+            );
+        }
+    }
+
+    #[test]
+    fn forbidden_and_required_chars_reject_otherwise_matching_addresses() {
+        let address = format!("{BODY}pump");
+        let mut config = MatchConfig::new(&suffixes(&["pump"]));
+        assert!(config.matches(&address));
+
+        config.forbidden_chars = Some("xyz".to_string());
+        assert!(!config.matches(&address));
+
+        config.forbidden_chars = None;
+        config.required_chars = Some("pum".to_string());
+        assert!(!config.matches(&address));
+        assert!(config.matches("pumpumpump"));
+    }
+
+    #[test]
+    fn address_length_filters_exclude_short_and_long_addresses() {
+        // Leading zero bytes make some addresses shorter than the usual 43-44 characters
+        let short = "1111Ld97TXJSDpbD5jBkheTqA83TZRpump";
+        let full = format!("{BODY}pump");
+        let config = MatchConfig {
+            min_address_len: Some(44),
+            ..MatchConfig::new(&suffixes(&["pump"]))
+        };
+        assert_eq!(full.len(), 44);
+        assert!(config.matches(&full));
+        assert!(!config.matches(short));
+
+        let config = MatchConfig {
+            max_address_len: Some(40),
+            ..MatchConfig::new(&suffixes(&["pump"]))
+        };
+        assert!(config.matches(short));
+        assert!(!config.matches(&full));
+    }
+
+    #[test]
+    fn fuzzy_matches_within_the_max_distance_at_either_end() {
+        let fuzzy = |max_distance| FuzzyMatch {
+            target: "pump".to_string(),
+            max_distance,
+        };
+        let exact = format!("{BODY}pump");
+        let one_edit = format!("{BODY}pmp");
+        let two_edits = format!("pXmq{BODY}");
+        let far = format!("{BODY}zzzz");
+
+        assert!(fuzzy(0).matches(&exact));
+        assert!(!fuzzy(0).matches(&one_edit));
+
+        assert!(fuzzy(1).matches(&exact));
+        assert!(fuzzy(1).matches(&one_edit));
+        assert!(!fuzzy(1).matches(&two_edits));
+
+        assert!(fuzzy(2).matches(&two_edits));
+        assert!(!fuzzy(2).matches(&far));
+        assert_eq!(fuzzy(2).best_distance(&one_edit), 1);
+    }
+
+    #[test]
+    fn min_match_len_rejects_matches_covering_too_few_characters() {
+        let fuzzy = MatchConfig {
+            fuzzy: Some(FuzzyMatch {
+                target: "pump".to_string(),
+                max_distance: 2,
+            }),
+            min_match_len: Some(3),
+            ..MatchConfig::new(&[])
+        };
+        let one_edit = format!("{BODY}pmp");
+        let two_edits = format!("{BODY}pXmq");
+        let matched_len = |config: &MatchConfig, address| config.find_match(address).map(|m| m.len);
+        assert_eq!(matched_len(&fuzzy, &one_edit), Some(3));
+        assert_eq!(matched_len(&fuzzy, &two_edits), None);
+        let unlimited = MatchConfig {
+            min_match_len: None,
+            ..fuzzy.clone()
+        };
+        assert_eq!(matched_len(&unlimited, &two_edits), Some(2));
+
+        let exact = MatchConfig {
+            min_match_len: Some(3),
+            ..MatchConfig::new(&suffixes(&["ab", "xyz"]))
+        };
+        assert!(!exact.matches(&format!("{BODY}ab")));
+        assert!(exact.matches(&format!("{BODY}xyz")));
+    }
+
+    #[test]
+    fn matches_table_over_synthetic_addresses() {
+        let pump = MatchConfig::new(&suffixes(&["pump"]));
+        let several = MatchConfig::new(&suffixes(&["pump", "a"]));
+        let nested = MatchConfig::new(&suffixes(&["mp", "pump"]));
+        let prefix_all = MatchConfig {
+            prefix: Some("7x".to_string()),
+            ..pump.clone()
+        };
+        let prefix_any = MatchConfig {
+            mode: MatchMode::Any,
+            ..prefix_all.clone()
+        };
+        let body_pump = format!("{BODY}pump");
+        let body_a = format!("{BODY}a");
+        let other_start = format!("8{}pump", &BODY[1..]);
+
+        let cases: &[(&MatchConfig, &str, Option<&str>)] = &[
+            (&pump, "", None),
+            (&pump, "p", None),
+            (&pump, "pum", None),
+            (&pump, "pump", Some("pump")),
+            (&pump, &body_pump, Some("pump")),
+            (&pump, BODY, None),
+            (&pump, "pumpX", None),
+            (&pump, "PUMP", None),
+            (&several, "", None),
+            (&several, "a", Some("a")),
+            (&several, &body_a, Some("a")),
+            (&several, "pumpa", Some("a")),
+            // The longer suffix labels a find that ends with both
+            (&nested, &body_pump, Some("pump")),
+            (&nested, "mp", Some("mp")),
+            (&several, &body_pump, Some("pump")),
+            (&prefix_all, &body_pump, Some("pump")),
+            (&prefix_all, &other_start, None),
+            (&prefix_all, "7x", None),
+            (&prefix_any, "7x", Some("7x")),
+            (&prefix_any, &other_start, Some("pump")),
+            (&prefix_any, "", None),
+            (&prefix_any, "7", None),
+        ];
+        for (config, address, expected) in cases {
+            assert_eq!(
+                config.matched_pattern(address),
+                *expected,
+                "{address:?} with {:?}",
+                config.patterns()
+            );
+            assert_eq!(config.matches(address), expected.is_some());
+        }
+    }
+
+    #[test]
+    fn smart_case_ignores_case_only_for_lowercase_patterns() {
+        let addresses = ["pump", "Pump", "PUMP", "pUmP"].map(|end| format!("{BODY}{end}"));
+        let cases = [
+            ("pump", [true, true, true, true]),
+            ("Pump", [false, true, false, false]),
+            ("PUMP", [false, false, true, false]),
+        ];
+        for (pattern, expected) in cases {
+            let config = MatchConfig {
+                smart_case: true,
+                ..MatchConfig::new(&suffixes(&[pattern]))
+            };
+            for (address, expected) in addresses.iter().zip(expected) {
+                assert_eq!(config.matches(address), expected, "{pattern} vs {address}");
+            }
+
+            // Without smart case every pattern is exact
+            let config = MatchConfig::new(&suffixes(&[pattern]));
+            for address in &addresses {
+                assert_eq!(config.matches(address), address.ends_with(pattern));
+            }
+        }
+
+        let config = MatchConfig {
+            smart_case: true,
+            prefix: Some("7xk".to_string()),
+            ..MatchConfig::new(&suffixes(&["Pump"]))
+        };
+        assert!(config.matches(&format!("{BODY}Pump")));
+        let config = MatchConfig {
+            prefix: Some("7XK".to_string()),
+            ..config
+        };
+        assert!(!config.matches(&format!("{BODY}Pump")));
+    }
+
+    #[test]
+    fn repeat_and_distinct_filters_hold_at_their_limits() {
+        assert_eq!(longest_run(""), 0);
+        assert_eq!(longest_run("a"), 1);
+        assert_eq!(longest_run("a111b"), 3);
+        assert_eq!(longest_run("aabbb"), 3);
+        assert_eq!(distinct_chars(""), 0);
+        assert_eq!(distinct_chars("pump"), 3);
+        assert_eq!(distinct_chars("pumpPUMP"), 6);
+
+        // A run of exactly `max_repeat` passes, one more fails
+        let three = format!("{BODY}111pump");
+        let four = format!("{BODY}1111pump");
+        assert_eq!(longest_run(&three), 3);
+        assert_eq!(longest_run(&four), 4);
+        let config = MatchConfig {
+            max_repeat: Some(3),
+            ..MatchConfig::new(&suffixes(&["pump"]))
+        };
+        assert!(config.matches(&three));
+        assert!(!config.matches(&four));
+
+        // Exactly `min_distinct` different characters passes, one fewer fails
+        let address = format!("{BODY}pump");
+        let distinct = distinct_chars(&address);
+        let config = |min| MatchConfig {
+            min_distinct: Some(min),
+            ..MatchConfig::new(&suffixes(&["pump"]))
+        };
+        assert!(config(distinct).matches(&address));
+        assert!(!config(distinct + 1).matches(&address));
+        assert!(!config(4).matches("pumppump"));
+        assert!(config(3).matches("pumppump"));
+    }
+
+    #[test]
+    fn min_entropy_rejects_low_entropy_addresses() {
+        assert_eq!(address_entropy("1111"), 0.0);
+        assert_eq!(address_entropy("abcd"), 2.0);
+
+        let low = format!("{}pump", "1".repeat(40));
+        let high = format!("{BODY}pump");
+        assert!(address_entropy(&low) < 1.0, "{}", address_entropy(&low));
+        assert!(address_entropy(&high) > 4.0, "{}", address_entropy(&high));
+
+        let config = MatchConfig {
+            min_entropy: Some(4.0),
+            ..MatchConfig::new(&suffixes(&["pump"]))
+        };
+        assert!(config.matches(&high));
+        assert!(!config.matches(&low));
+
+        let config = MatchConfig {
+            min_entropy: Some(address_entropy(&high)),
+            ..config
+        };
+        assert!(config.matches(&high));
+    }
+
+    #[test]
+    fn smart_case_dedups_and_flags_case_variants() {
+        let mut patterns = suffixes(&["Pump", "moon", "pump", "PUMP", "moon"]);
+        let removed = dedup_patterns(&mut patterns, false);
+        assert_eq!(patterns, ["Pump", "moon", "pump", "PUMP"]);
+        assert_eq!(removed, [("moon".to_string(), "moon".to_string())]);
+
+        let mut patterns = suffixes(&["Pump", "moon", "pump", "PUMP", "moon"]);
+        let removed = dedup_patterns(&mut patterns, true);
+        assert_eq!(patterns, ["moon", "pump"]);
+        assert_eq!(
+            removed,
+            [
+                ("Pump".to_string(), "pump".to_string()),
+                ("PUMP".to_string(), "pump".to_string()),
+                ("moon".to_string(), "moon".to_string()),
+            ]
+        );
+
+        // Exact variants only differ from each other, even under smart case
+        let mut patterns = suffixes(&["Pump", "PUMP"]);
+        assert!(dedup_patterns(&mut patterns, true).is_empty());
+
+        let config = |patterns: &[&str], smart_case| MatchConfig {
+            smart_case,
+            ..MatchConfig::new(&suffixes(patterns))
+        };
+        assert!(config(&["Pump", "pump"], false)
+            .subsumed_suffixes()
+            .is_empty());
+        assert_eq!(
+            config(&["Pump", "pump"], true).subsumed_suffixes(),
+            [("Pump", "pump")]
+        );
+        assert_eq!(
+            config(&["pump", "XPUMP", "Pump"], true).subsumed_suffixes(),
+            [("XPUMP", "pump"), ("Pump", "pump")]
+        );
+        assert!(config(&["Pump", "PUMP"], true)
+            .subsumed_suffixes()
+            .is_empty());
+    }
+
+    #[test]
+    fn vanity_score_follows_its_documented_rules() {
+        let found = |pattern, condition, len| PatternMatch {
+            pattern,
+            condition,
+            len,
+        };
+        let plain = format!("{BODY}pump");
+        assert_eq!(longest_run(&plain), 1);
+
+        // Suffix only: 10 points per matched character
+        let suffix = found("pump", MatchedCondition::Suffix, 4);
+        assert_eq!(vanity_score(&plain, &suffix), 40);
+
+        // A matched prefix adds 5, alone or with the suffix
+        let prefix = found("7xK", MatchedCondition::Prefix, 3);
+        assert_eq!(vanity_score(&plain, &prefix), 35);
+        let both = found("7xK…pump", MatchedCondition::Both, 7);
+        assert_eq!(vanity_score(&plain, &both), 75);
+
+        // Every character repeating the one before it adds 3: "pp" is one pair,
+        // "1111" three
+        assert_eq!(vanity_score(&format!("{BODY}ppump"), &suffix), 43);
+        assert_eq!(vanity_score(&format!("1111{BODY}pump"), &suffix), 49);
+        assert_eq!(vanity_score(&format!("11{BODY}ppump"), &both), 81);
+    }
+}
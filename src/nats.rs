@@ -0,0 +1,197 @@
+use crate::error::{Error, Result};
+use crate::matcher::MatchedCondition;
+use crate::sink::{WalletDocument, WalletSink};
+use async_trait::async_trait;
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::TcpStream;
+use tokio::sync::Mutex;
+
+/// Port used when the NATS URL doesn't specify one
+const DEFAULT_NATS_PORT: u16 = 4222;
+
+/// The event published for every found wallet
+#[derive(Serialize)]
+struct FoundWalletEvent<'a> {
+    public_key: &'a str,
+    matched_pattern: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    matched_condition: Option<MatchedCondition>,
+    created_at: chrono::DateTime<chrono::Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    private_key: Option<&'a str>,
+}
+
+/// Publishes a JSON event per found wallet to a NATS subject.
+///
+/// Speaks the plain-text NATS client protocol over TCP, which is all a
+/// fire-and-forget publisher needs (no TLS or authentication).
+pub struct NatsSink {
+    address: String,
+    subject: String,
+    include_private_key: bool,
+    writer: Option<Arc<Mutex<OwnedWriteHalf>>>,
+    /// Set by the reader task once the server closes the connection
+    closed: Arc<AtomicBool>,
+}
+
+impl NatsSink {
+    pub async fn new(url: &str, subject: &str, include_private_key: bool) -> Result<Self> {
+        let host = url.strip_prefix("nats://").unwrap_or(url);
+        let address = if host.contains(':') {
+            host.to_string()
+        } else {
+            format!("{}:{}", host, DEFAULT_NATS_PORT)
+        };
+
+        let mut sink = Self {
+            address,
+            subject: subject.to_string(),
+            include_private_key,
+            writer: None,
+            closed: Arc::new(AtomicBool::new(true)),
+        };
+        sink.connect().await?;
+        log::info!("Connected to NATS at {}, publishing to '{}'", url, subject);
+
+        Ok(sink)
+    }
+
+    /// Perform the INFO/CONNECT handshake and wait for the server to acknowledge it
+    async fn connect(&mut self) -> Result<()> {
+        let stream = TcpStream::connect(&self.address).await?;
+        let (reader, mut writer) = stream.into_split();
+        let mut reader = BufReader::new(reader);
+
+        let mut line = String::new();
+        reader.read_line(&mut line).await?;
+        if !line.starts_with("INFO") {
+            return Err(Error::Connect(format!(
+                "unexpected NATS greeting: {}",
+                line.trim_end()
+            )));
+        }
+
+        let connect = serde_json::json!({
+            "verbose": false,
+            "pedantic": false,
+            "name": env!("CARGO_PKG_NAME"),
+            "lang": "rust",
+            "version": env!("CARGO_PKG_VERSION"),
+            "protocol": 0,
+        });
+        writer
+            .write_all(format!("CONNECT {}\r\nPING\r\n", connect).as_bytes())
+            .await?;
+
+        loop {
+            line.clear();
+            if reader.read_line(&mut line).await? == 0 {
+                return Err(Error::Connect(
+                    "NATS server closed the connection during handshake".to_string(),
+                ));
+            }
+            match line.trim_end() {
+                "PONG" => break,
+                error if error.starts_with("-ERR") => {
+                    return Err(Error::Connect(format!(
+                        "NATS server rejected connection: {}",
+                        error
+                    )));
+                }
+                _ => {}
+            }
+        }
+
+        let writer = Arc::new(Mutex::new(writer));
+        let closed = Arc::new(AtomicBool::new(false));
+        tokio::spawn(answer_pings(reader, writer.clone(), closed.clone()));
+        self.writer = Some(writer);
+        self.closed = closed;
+
+        Ok(())
+    }
+
+    async fn publish(&self, payload: &[u8]) -> Result<()> {
+        // Writes to a socket the server already closed can still appear to
+        // succeed, so check what the reader task saw first
+        let writer = self
+            .writer
+            .as_ref()
+            // A status flag with no data behind it, so Relaxed is enough
+            .filter(|_| !self.closed.load(Ordering::Relaxed))
+            .ok_or_else(|| Error::Connect("not connected to NATS".to_string()))?;
+        let mut message = format!("PUB {} {}\r\n", self.subject, payload.len()).into_bytes();
+        message.extend_from_slice(payload);
+        message.extend_from_slice(b"\r\n");
+
+        let mut writer = writer.lock().await;
+        writer.write_all(&message).await?;
+        writer.flush().await?;
+        Ok(())
+    }
+}
+
+/// Keep the connection alive by answering server PINGs until it closes
+async fn answer_pings(
+    mut reader: BufReader<OwnedReadHalf>,
+    writer: Arc<Mutex<OwnedWriteHalf>>,
+    closed: Arc<AtomicBool>,
+) {
+    let mut line = String::new();
+    loop {
+        line.clear();
+        match reader.read_line(&mut line).await {
+            Ok(0) | Err(_) => {
+                log::warn!("NATS connection closed");
+                closed.store(true, Ordering::Relaxed);
+                return;
+            }
+            Ok(_) => {}
+        }
+
+        match line.trim_end() {
+            "PING" => {
+                let pong = writer.lock().await.write_all(b"PONG\r\n").await;
+                if pong.is_err() {
+                    return;
+                }
+            }
+            error if error.starts_with("-ERR") => log::error!("NATS server error: {}", error),
+            _ => {}
+        }
+    }
+}
+
+#[async_trait]
+impl WalletSink for NatsSink {
+    async fn save_wallet(&mut self, wallet: &WalletDocument) -> Result<()> {
+        let event = FoundWalletEvent {
+            public_key: &wallet.public_key,
+            matched_pattern: &wallet.matched_pattern,
+            matched_condition: wallet.matched_condition,
+            created_at: wallet.created_at,
+            private_key: self
+                .include_private_key
+                .then_some(wallet.private_key.as_str()),
+        };
+        let payload = serde_json::to_vec(&event)?;
+
+        if let Err(e) = self.publish(&payload).await {
+            // The connection may have dropped since the last find, so reconnect once
+            log::warn!("NATS publish failed ({}), reconnecting", e);
+            self.connect().await?;
+            self.publish(&payload).await?;
+        }
+        log::info!("Wallet event published to NATS: {}", wallet.public_key);
+
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "NATS"
+    }
+}
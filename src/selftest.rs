@@ -0,0 +1,157 @@
+use crate::matcher::{self, Base58Alphabet, MatchConfig};
+use crate::wallet_generator::WalletGenerator;
+use solana_sdk::signature::Signer;
+
+/// Keypairs generated by the keypair checks
+const SAMPLE_WALLETS: usize = 100;
+
+/// The outcome of one invariant check
+pub struct Check {
+    pub name: &'static str,
+    /// What went wrong, if the check failed
+    pub result: Result<(), String>,
+}
+
+/// Run every invariant check the search depends on: that keys come out the right
+/// size, that base58 round-trips and that the matcher accepts and rejects what it
+/// should. None of them needs a sink or the network.
+pub fn run() -> Vec<Check> {
+    vec![
+        check(
+            "Generated keypairs are 64 bytes with a 32-byte public key",
+            keypair_sizes,
+        ),
+        check(
+            "Base58 round-trips and matches a known vector",
+            base58_round_trip,
+        ),
+        check(
+            "Private key strings decode back to the same keypair",
+            private_key_round_trip,
+        ),
+        check(
+            "The matcher accepts and rejects known addresses",
+            matcher_cases,
+        ),
+        check(
+            "Patterns with non-base58 characters are rejected",
+            pattern_validation,
+        ),
+    ]
+}
+
+fn check(name: &'static str, run: fn() -> Result<(), String>) -> Check {
+    Check {
+        name,
+        result: run(),
+    }
+}
+
+fn sample_generator() -> WalletGenerator {
+    WalletGenerator::new(MatchConfig::new(&[]))
+}
+
+fn keypair_sizes() -> Result<(), String> {
+    let mut generator = sample_generator();
+    for _ in 0..SAMPLE_WALLETS {
+        let wallet = generator.generate_wallet();
+        let keypair_len = wallet.keypair.to_bytes().len();
+        if keypair_len != 64 {
+            return Err(format!(
+                "keypair of {} is {} bytes",
+                wallet.pubkey, keypair_len
+            ));
+        }
+        let pubkey = bs58::decode(&wallet.pubkey)
+            .into_vec()
+            .map_err(|e| format!("public key {} is not base58: {}", wallet.pubkey, e))?;
+        if pubkey != wallet.keypair.pubkey().to_bytes() {
+            return Err(format!(
+                "public key {} decodes to {} bytes that aren't the keypair's",
+                wallet.pubkey,
+                pubkey.len()
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn base58_round_trip() -> Result<(), String> {
+    // Test vector from the Base58 draft specification
+    let encoded = bs58::encode(b"Hello World!").into_string();
+    if encoded != "2NEpo7TZRRrLZSi2U" {
+        return Err(format!("'Hello World!' encoded as {}", encoded));
+    }
+
+    let mut generator = sample_generator();
+    for _ in 0..SAMPLE_WALLETS {
+        let bytes = generator.generate_wallet().keypair.pubkey().to_bytes();
+        let encoded = bs58::encode(bytes).into_string();
+        let decoded = bs58::decode(&encoded)
+            .into_vec()
+            .map_err(|e| format!("{} didn't decode: {}", encoded, e))?;
+        if decoded != bytes {
+            return Err(format!("{} decoded to different bytes", encoded));
+        }
+    }
+    Ok(())
+}
+
+fn private_key_round_trip() -> Result<(), String> {
+    let mut generator = sample_generator();
+    for _ in 0..SAMPLE_WALLETS {
+        let wallet = generator.generate_wallet();
+        let private_key = WalletGenerator::get_private_key_string(&wallet.keypair);
+        WalletGenerator::verify_private_key(&wallet.pubkey, &private_key)
+            .map_err(|e| format!("{}: {}", wallet.pubkey, e))?;
+    }
+    Ok(())
+}
+
+fn matcher_cases() -> Result<(), String> {
+    // Real addresses are 32-44 characters; the matcher only looks at the ends
+    const BODY: &str = "7xKXtg2CW87d97TXJSDpbD5jBkheTqA83TZRuJosg";
+
+    let exact = MatchConfig::new(&["pump".to_string()]);
+    let smart_case = MatchConfig {
+        smart_case: true,
+        ..exact.clone()
+    };
+    let with_prefix = MatchConfig {
+        prefix: Some("7xK".to_string()),
+        ..exact.clone()
+    };
+    let cases = [
+        (&exact, format!("{}pump", BODY), true),
+        (&exact, format!("{}PUMP", BODY), false),
+        (&exact, format!("{}pmp", BODY), false),
+        (&exact, format!("pump{}", BODY), false),
+        (&smart_case, format!("{}PuMp", BODY), true),
+        (&with_prefix, format!("{}pump", BODY), true),
+        (&with_prefix, format!("8{}pump", &BODY[1..]), false),
+    ];
+    for (config, address, expected) in cases {
+        if config.matches(&address) != expected {
+            return Err(format!(
+                "{} should {}match {:?}",
+                address,
+                if expected { "" } else { "not " },
+                config.patterns()
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn pattern_validation() -> Result<(), String> {
+    for pattern in ["pump", "ABC", "xyz9"] {
+        matcher::validate_pattern(pattern, Base58Alphabet::Bitcoin)
+            .map_err(|e| format!("'{}' was rejected: {}", pattern, e))?;
+    }
+    for pattern in ["p0mp", "Ola", "Il"] {
+        if matcher::validate_pattern(pattern, Base58Alphabet::Bitcoin).is_ok() {
+            return Err(format!("'{}' was accepted", pattern));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,550 @@
+use crate::error::{Error, Result};
+use crate::matcher::{self, MatchedCondition, PatternMatch};
+use crate::wallet_generator::{GeneratedWallet, WalletGenerator};
+use async_trait::async_trait;
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use zeroize::{Zeroize, Zeroizing};
+
+/// Version of the [`WalletDocument`] layout written by this build. Bump it when a
+/// change needs old records migrated; records from before versioning read as 0.
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WalletDocument {
+    /// [`SCHEMA_VERSION`] of the build that saved the record
+    #[serde(default)]
+    pub schema_version: u32,
+    pub public_key: String,
+    pub private_key: String,
+    #[serde(default)]
+    pub matched_pattern: String,
+    /// Whether the prefix, the suffix or both matched
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub matched_condition: Option<MatchedCondition>,
+    /// [`matcher::vanity_score`] of the address, for picking the best-looking finds
+    #[serde(default)]
+    pub vanity_score: u32,
+    /// Total attempts across all workers when the wallet was found; counters are
+    /// batched per worker, so it's approximate but keeps finds in order
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub found_at_attempt: Option<u64>,
+    /// With `--progressive`, how many characters of the target the address ends with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub matched_len: Option<usize>,
+    /// BIP39 phrase the wallet was derived from, in mnemonic mode
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mnemonic: Option<String>,
+    /// BIP44 path of the matching account within the mnemonic's seed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub derivation_path: Option<String>,
+    /// With `--match-ata`, the associated token account that matched the pattern
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub associated_token_account: Option<String>,
+    /// With `--group-by-seed`, every scanned account of the mnemonic, the matching one included
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub accounts: Option<Vec<SeedAccount>>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// One account derived from a found wallet's mnemonic
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SeedAccount {
+    pub derivation_path: String,
+    pub public_key: String,
+    pub private_key: String,
+}
+
+impl SeedAccount {
+    pub fn from_wallet(wallet: &GeneratedWallet) -> Self {
+        Self {
+            derivation_path: wallet
+                .recovery
+                .as_ref()
+                .map(|recovery| recovery.derivation_path.clone())
+                .unwrap_or_default(),
+            public_key: wallet.pubkey.clone(),
+            private_key: WalletGenerator::get_private_key_string(&wallet.keypair).to_string(),
+        }
+    }
+}
+
+impl Drop for SeedAccount {
+    fn drop(&mut self) {
+        self.private_key.zeroize();
+    }
+}
+
+impl WalletDocument {
+    /// Build the document for a found wallet, including how to recover it in mnemonic mode
+    pub fn from_wallet(wallet: &GeneratedWallet, found: &PatternMatch) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            public_key: wallet.pubkey.clone(),
+            private_key: WalletGenerator::get_private_key_string(&wallet.keypair).to_string(),
+            matched_pattern: found.pattern.to_string(),
+            matched_condition: Some(found.condition),
+            vanity_score: matcher::vanity_score(wallet.matched_address(), found),
+            found_at_attempt: None,
+            matched_len: None,
+            mnemonic: wallet
+                .recovery
+                .as_ref()
+                .map(|recovery| recovery.mnemonic.to_string()),
+            derivation_path: wallet
+                .recovery
+                .as_ref()
+                .map(|recovery| recovery.derivation_path.clone()),
+            associated_token_account: wallet.associated_token_account.clone(),
+            accounts: None,
+            created_at: chrono::Utc::now(),
+        }
+    }
+}
+
+// Documents carry the private key and mnemonic, so wipe them once dropped
+impl Drop for WalletDocument {
+    fn drop(&mut self) {
+        self.private_key.zeroize();
+        self.mnemonic.zeroize();
+    }
+}
+
+/// First bytes of every gzip member
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Read wallets back from a JSON-lines file written by [`FileSink`], compressed or not
+pub fn read_wallet_file(path: &Path) -> Result<Vec<WalletDocument>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let reader: Box<dyn BufRead> = if reader.fill_buf()?.starts_with(&GZIP_MAGIC) {
+        // A file that was appended to across runs holds one gzip member per run
+        Box::new(BufReader::new(MultiGzDecoder::new(reader)))
+    } else {
+        Box::new(reader)
+    };
+    let mut wallets = Vec::new();
+
+    for line in reader.lines() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            wallets.push(serde_json::from_str(&line)?);
+        }
+    }
+
+    Ok(wallets)
+}
+
+/// A destination that found vanity wallets are written to
+#[async_trait]
+pub trait WalletSink: Send {
+    /// Persist a single found wallet
+    async fn save_wallet(&mut self, wallet: &WalletDocument) -> Result<()>;
+
+    /// Whether a wallet with this public key is already stored. Sinks that can't tell
+    /// cheaply, such as files and publishers, say no, so the wallet is saved again.
+    async fn contains(&mut self, _public_key: &str) -> Result<bool> {
+        Ok(false)
+    }
+
+    /// Short name used when logging which sink failed
+    fn name(&self) -> &'static str {
+        "sink"
+    }
+
+    /// Flush any buffered writes before shutting down
+    async fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// `path` with `.gz` appended, unless it already ends with it
+pub fn gzip_path(path: &Path) -> PathBuf {
+    if path.extension().is_some_and(|extension| extension == "gz") {
+        return path.to_path_buf();
+    }
+    let mut name = path.as_os_str().to_os_string();
+    name.push(".gz");
+    PathBuf::from(name)
+}
+
+/// Where a [`FileSink`] writes to
+enum FileOutput {
+    Plain(BufWriter<File>),
+    /// Exactly one of the two is set: the encoder of the open gzip member, or the file
+    /// between members. Each sink flush ends the member, so the file is valid gzip
+    /// after a graceful shutdown, and a later save simply starts another member.
+    Gzip {
+        encoder: Option<GzEncoder<BufWriter<File>>>,
+        file: Option<BufWriter<File>>,
+    },
+}
+
+impl FileOutput {
+    fn writer(&mut self) -> &mut dyn Write {
+        match self {
+            Self::Plain(writer) => writer,
+            Self::Gzip { encoder, file } => encoder.get_or_insert_with(|| {
+                let file = file.take().expect("the file is kept between gzip members");
+                GzEncoder::new(file, Compression::default())
+            }),
+        }
+    }
+
+    /// Write out everything buffered, ending the gzip member if one is open
+    fn finish(&mut self) -> Result<()> {
+        match self {
+            Self::Plain(writer) => writer.flush()?,
+            Self::Gzip { encoder, file } => {
+                if let Some(encoder) = encoder.take() {
+                    let mut writer = encoder.finish()?;
+                    writer.flush()?;
+                    *file = Some(writer);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Appends found wallets to a file as JSON lines, optionally gzip-compressed.
+///
+/// ```
+/// use solana_sdk::signature::Keypair;
+/// use solana_vanity_wallet::matcher::MatchConfig;
+/// use solana_vanity_wallet::sink::{read_wallet_file, FileSink, WalletDocument, WalletSink};
+/// use solana_vanity_wallet::wallet_generator::{SequenceKeypairs, WalletGenerator};
+///
+/// let path = std::env::temp_dir().join(format!("wallets-{}.jsonl.gz", std::process::id()));
+/// let keypairs = (0..3).map(|_| Keypair::new()).collect();
+/// let mut generator = WalletGenerator::new(MatchConfig::new(&[String::new()]))
+///     .with_keypair_source(Box::new(SequenceKeypairs::new(keypairs)));
+///
+/// let mut sink = FileSink::new_gzip(&path).unwrap();
+/// let mut public_keys = Vec::new();
+/// tokio::runtime::Runtime::new().unwrap().block_on(async {
+///     for _ in 0..3 {
+///         let hit = generator.try_one().expect("the empty suffix matches every key");
+///         let document = WalletDocument::from_wallet(&hit.wallet, &hit.found);
+///         sink.save_wallet(&document).await.unwrap();
+///         public_keys.push(document.public_key.clone());
+///     }
+///     sink.flush().await.unwrap();
+/// });
+/// drop(sink);
+///
+/// let read: Vec<_> = read_wallet_file(&path)
+///     .unwrap()
+///     .iter()
+///     .map(|wallet| wallet.public_key.clone())
+///     .collect();
+/// assert_eq!(read, public_keys);
+/// std::fs::remove_file(path).unwrap();
+/// ```
+pub struct FileSink {
+    output: FileOutput,
+}
+
+impl FileSink {
+    pub fn new(path: &Path) -> Result<Self> {
+        log::info!("Writing wallets to {}", path.display());
+        Ok(Self {
+            output: FileOutput::Plain(Self::open(path)?),
+        })
+    }
+
+    /// Write a gzip-compressed file instead. Appending to an existing one adds a gzip
+    /// member, which `gunzip`, `zcat` and [`read_wallet_file`] read as one stream.
+    pub fn new_gzip(path: &Path) -> Result<Self> {
+        log::info!("Writing compressed wallets to {}", path.display());
+        Ok(Self {
+            output: FileOutput::Gzip {
+                encoder: None,
+                file: Some(Self::open(path)?),
+            },
+        })
+    }
+
+    fn open(path: &Path) -> Result<BufWriter<File>> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(BufWriter::new(file))
+    }
+}
+
+#[async_trait]
+impl WalletSink for FileSink {
+    async fn save_wallet(&mut self, wallet: &WalletDocument) -> Result<()> {
+        let writer = self.output.writer();
+        serde_json::to_writer(&mut *writer, wallet)?;
+        writer.write_all(b"\n")?;
+        // Found wallets are rare and irreplaceable, so flush each one immediately. For
+        // gzip this is a sync flush: the wallet is on disk, but the member isn't closed.
+        writer.flush()?;
+        log::info!("Wallet saved to file: {}", wallet.public_key);
+
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        self.output.finish()
+    }
+
+    fn name(&self) -> &'static str {
+        "file"
+    }
+}
+
+/// Keeps found wallets in memory, for tests and for benchmarking the save path
+/// without a database. Clones share the same wallets, which are lost on exit.
+///
+/// ```
+/// use solana_sdk::signature::{Keypair, Signer};
+/// use solana_vanity_wallet::matcher::MatchConfig;
+/// use solana_vanity_wallet::sink::{InMemorySink, WalletDocument, WalletSink};
+/// use solana_vanity_wallet::wallet_generator::{SequenceKeypairs, WalletGenerator};
+///
+/// let keypair = Keypair::new();
+/// let address = keypair.pubkey().to_string();
+/// let suffix = address[address.len() - 3..].to_string();
+/// let mut generator = WalletGenerator::new(MatchConfig::new(&[suffix]))
+///     .with_keypair_source(Box::new(SequenceKeypairs::new(vec![keypair])));
+/// let hit = generator.try_one().expect("the only keypair ends with the suffix");
+/// let document = WalletDocument::from_wallet(&hit.wallet, &hit.found);
+///
+/// let sink = InMemorySink::new();
+/// let mut writer_side = sink.clone();
+/// tokio::runtime::Runtime::new()
+///     .unwrap()
+///     .block_on(writer_side.save_wallet(&document))
+///     .unwrap();
+///
+/// assert_eq!(sink.len(), 1);
+/// assert_eq!(sink.take()[0].public_key, address);
+/// ```
+#[derive(Clone, Default)]
+pub struct InMemorySink {
+    wallets: Arc<Mutex<Vec<WalletDocument>>>,
+}
+
+impl InMemorySink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How many wallets have been saved, through this sink or any clone of it
+    pub fn len(&self) -> usize {
+        self.wallets.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Remove and return the saved wallets, oldest first
+    pub fn take(&self) -> Vec<WalletDocument> {
+        std::mem::take(&mut *self.wallets.lock().unwrap())
+    }
+}
+
+#[async_trait]
+impl WalletSink for InMemorySink {
+    async fn save_wallet(&mut self, wallet: &WalletDocument) -> Result<()> {
+        // Round-trip through JSON so benchmarks pay for serialization like a real sink
+        let json = Zeroizing::new(serde_json::to_string(wallet)?);
+        let copy = serde_json::from_str(&json)?;
+        self.wallets.lock().unwrap().push(copy);
+        log::info!("Wallet kept in memory: {}", wallet.public_key);
+
+        Ok(())
+    }
+
+    async fn contains(&mut self, public_key: &str) -> Result<bool> {
+        Ok(self
+            .wallets
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|wallet| wallet.public_key == public_key))
+    }
+
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+}
+
+/// Fans each wallet out to several sinks, e.g. MongoDB plus a local file backup.
+///
+/// Every sink gets the wallet even if another one fails. A save fails if any sink
+/// failed, and a retry of the same wallet only goes to the sinks that haven't stored
+/// it yet, so appending sinks don't get duplicates.
+pub struct MultiSink {
+    sinks: Vec<Box<dyn WalletSink>>,
+    /// Public key of the last wallet that failed somewhere, and which sinks stored it
+    pending: Option<(String, Vec<bool>)>,
+}
+
+impl MultiSink {
+    pub fn new(sinks: Vec<Box<dyn WalletSink>>) -> Self {
+        Self {
+            sinks,
+            pending: None,
+        }
+    }
+}
+
+#[async_trait]
+impl WalletSink for MultiSink {
+    async fn save_wallet(&mut self, wallet: &WalletDocument) -> Result<()> {
+        let (_, saved) = match &mut self.pending {
+            Some(pending) if pending.0 == wallet.public_key => pending,
+            pending => pending.insert((wallet.public_key.clone(), vec![false; self.sinks.len()])),
+        };
+
+        let mut errors = Vec::new();
+        for (sink, saved) in self.sinks.iter_mut().zip(saved.iter_mut()) {
+            if *saved {
+                continue;
+            }
+            match sink.save_wallet(wallet).await {
+                Ok(()) => *saved = true,
+                Err(e) => {
+                    log::warn!("Saving to {} failed: {}", sink.name(), e);
+                    errors.push(e);
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            self.pending = None;
+            return Ok(());
+        }
+        // Only give up on retrying when none of the failures could be fixed by a retry
+        if errors
+            .iter()
+            .all(|e| matches!(e, Error::PermanentSave { .. }))
+        {
+            return Err(Error::PermanentSave {
+                reason: "every failing sink rejected the wallet",
+                source: Box::new(Error::Sinks(errors)),
+            });
+        }
+        Err(Error::Sinks(errors))
+    }
+
+    /// Stored only once every sink has the wallet; saving again fills in the others
+    async fn contains(&mut self, public_key: &str) -> Result<bool> {
+        for sink in &mut self.sinks {
+            if !sink.contains(public_key).await? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        let mut errors = Vec::new();
+        for sink in &mut self.sinks {
+            if let Err(e) = sink.flush().await {
+                log::warn!("Flushing {} failed: {}", sink.name(), e);
+                errors.push(e);
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Sinks(errors))
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "all sinks"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matcher::MatchConfig;
+    use crate::wallet_generator::SequenceKeypairs;
+    use solana_sdk::signature::Keypair;
+
+    /// A sink whose server is never reachable
+    struct FailingSink;
+
+    #[async_trait]
+    impl WalletSink for FailingSink {
+        async fn save_wallet(&mut self, _wallet: &WalletDocument) -> Result<()> {
+            Err(Error::Connect("sink is down".to_string()))
+        }
+
+        async fn contains(&mut self, _public_key: &str) -> Result<bool> {
+            Ok(false)
+        }
+
+        fn name(&self) -> &'static str {
+            "failing"
+        }
+    }
+
+    /// The document of a wallet found by an empty suffix, which matches every key
+    fn found_document() -> WalletDocument {
+        let mut generator = WalletGenerator::new(MatchConfig::new(&[String::new()]))
+            .with_keypair_source(Box::new(SequenceKeypairs::new(vec![Keypair::new()])));
+        let hit = generator.try_one().unwrap();
+        WalletDocument::from_wallet(&hit.wallet, &hit.found)
+    }
+
+    #[test]
+    fn found_wallets_carry_the_current_schema_version() {
+        let document = found_document();
+        assert_eq!(document.schema_version, SCHEMA_VERSION);
+
+        let mut json = serde_json::to_value(&document).unwrap();
+        assert_eq!(json["schema_version"], SCHEMA_VERSION);
+        // Records saved before versioning have no field at all
+        json.as_object_mut().unwrap().remove("schema_version");
+        let old: WalletDocument = serde_json::from_value(json).unwrap();
+        assert_eq!(old.schema_version, 0);
+    }
+
+    #[tokio::test]
+    async fn multi_sink_saves_to_every_sink() {
+        let (first, second) = (InMemorySink::new(), InMemorySink::new());
+        let mut sink = MultiSink::new(vec![Box::new(first.clone()), Box::new(second.clone())]);
+        let document = found_document();
+
+        sink.save_wallet(&document).await.unwrap();
+        assert_eq!(first.take()[0].public_key, document.public_key);
+        assert_eq!(second.take()[0].public_key, document.public_key);
+    }
+
+    #[tokio::test]
+    async fn a_failing_sink_does_not_stop_the_others() {
+        let (first, second) = (InMemorySink::new(), InMemorySink::new());
+        let mut sink = MultiSink::new(vec![
+            Box::new(first.clone()),
+            Box::new(FailingSink),
+            Box::new(second.clone()),
+        ]);
+        let document = found_document();
+
+        let err = sink.save_wallet(&document).await.unwrap_err();
+        assert!(
+            matches!(&err, Error::Sinks(errors) if errors.len() == 1),
+            "{err}"
+        );
+        assert_eq!(first.len(), 1);
+        assert_eq!(second.len(), 1);
+        assert!(!sink.contains(&document.public_key).await.unwrap());
+
+        // A retry only goes to the sink that failed, so the others get no duplicate
+        sink.save_wallet(&document).await.unwrap_err();
+        assert_eq!(first.len(), 1);
+        assert_eq!(second.len(), 1);
+    }
+}
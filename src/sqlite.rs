@@ -0,0 +1,108 @@
+use crate::error::{Error, Result};
+use crate::sink::{WalletDocument, WalletSink};
+use async_trait::async_trait;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::path::Path;
+use zeroize::Zeroizing;
+
+/// Stores found wallets in a SQLCipher database, encrypted at rest with a passphrase
+pub struct SqliteSink {
+    connection: Connection,
+}
+
+impl SqliteSink {
+    /// Open (or create) the database at `path`, failing if `key` doesn't decrypt it
+    pub fn open(path: &Path, key: &str) -> Result<Self> {
+        let connection = Connection::open(path)?;
+
+        // Plain SQLite accepts and ignores the key pragma, so check for SQLCipher first
+        let cipher_version: Option<String> = connection
+            .query_row("PRAGMA cipher_version", [], |row| row.get(0))
+            .optional()?;
+        if cipher_version.is_none() {
+            return Err(Error::Encryption(
+                "This build's SQLite has no SQLCipher support, so the database can't be encrypted"
+                    .to_string(),
+            ));
+        }
+        connection.pragma_update(None, "key", key)?;
+
+        // The key is only checked once a page is read
+        connection
+            .query_row("SELECT count(*) FROM sqlite_master", [], |_| Ok(()))
+            .map_err(|e| {
+                Error::Encryption(format!(
+                    "Failed to unlock {}: wrong --sqlite-key, or not a SQLCipher database ({})",
+                    path.display(),
+                    e
+                ))
+            })?;
+
+        connection.execute(
+            "CREATE TABLE IF NOT EXISTS wallets (
+                public_key TEXT PRIMARY KEY,
+                matched_pattern TEXT NOT NULL,
+                created_at TEXT NOT NULL,
+                document TEXT NOT NULL
+            )",
+            [],
+        )?;
+        log::info!("Writing wallets to encrypted database {}", path.display());
+
+        Ok(Self { connection })
+    }
+
+    /// Read every stored wallet back, oldest first
+    pub fn read_all(&self) -> Result<Vec<WalletDocument>> {
+        let mut statement = self
+            .connection
+            .prepare("SELECT document FROM wallets ORDER BY created_at")?;
+        let documents = statement
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        documents
+            .iter()
+            .map(|document| Ok(serde_json::from_str(document)?))
+            .collect()
+    }
+}
+
+#[async_trait]
+impl WalletSink for SqliteSink {
+    async fn save_wallet(&mut self, wallet: &WalletDocument) -> Result<()> {
+        let document = Zeroizing::new(serde_json::to_string(wallet)?);
+        // Keyed on the public key like the MongoDB upsert, so retries never store a wallet twice
+        let inserted = self.connection.execute(
+            "INSERT OR IGNORE INTO wallets (public_key, matched_pattern, created_at, document)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![
+                wallet.public_key,
+                wallet.matched_pattern,
+                wallet.created_at.to_rfc3339(),
+                document.as_str(),
+            ],
+        )?;
+        match inserted {
+            0 => log::warn!(
+                "Wallet {} is already stored in the database",
+                wallet.public_key
+            ),
+            _ => log::info!("Wallet saved to encrypted database: {}", wallet.public_key),
+        }
+
+        Ok(())
+    }
+
+    async fn contains(&mut self, public_key: &str) -> Result<bool> {
+        Ok(self.connection.query_row(
+            "SELECT EXISTS(SELECT 1 FROM wallets WHERE public_key = ?1)",
+            params![public_key],
+            |row| row.get(0),
+        )?)
+    }
+
+    fn name(&self) -> &'static str {
+        "SQLite"
+    }
+}
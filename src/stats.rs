@@ -0,0 +1,511 @@
+use crate::error::Result;
+use chrono::{DateTime, Utc};
+use hdrhistogram::Histogram;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Exponential moving average of the wallet generation rate
+pub struct RateEma {
+    alpha: f64,
+    last_sample: Instant,
+    last_count: usize,
+    smoothed: Option<f64>,
+    /// Every unsmoothed sample, for the summary's percentiles
+    histogram: RateHistogram,
+}
+
+impl RateEma {
+    /// Create a tracker; `alpha` is the weight given to the newest sample (0 < alpha <= 1)
+    pub fn new(alpha: f64) -> Self {
+        Self {
+            alpha,
+            last_sample: Instant::now(),
+            last_count: 0,
+            smoothed: None,
+            histogram: RateHistogram::new(),
+        }
+    }
+
+    /// Record the total generated count and return the smoothed wallets/second
+    pub fn update(&mut self, count: usize) -> f64 {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_sample).as_secs_f64();
+        // Nothing generated since the last sample, e.g. the very first progress print
+        if elapsed <= 0.0 || count <= self.last_count {
+            return self.smoothed.unwrap_or(0.0);
+        }
+
+        let rate = count.saturating_sub(self.last_count) as f64 / elapsed;
+        self.histogram.record(rate);
+        let smoothed = match self.smoothed {
+            Some(previous) => self.alpha * rate + (1.0 - self.alpha) * previous,
+            None => rate,
+        };
+
+        self.last_sample = now;
+        self.last_count = count;
+        self.smoothed = Some(smoothed);
+        smoothed
+    }
+
+    /// The rate as of the last update, `None` before the first one
+    pub fn current(&self) -> Option<f64> {
+        self.smoothed
+    }
+
+    /// Percentiles of the unsmoothed rates sampled so far
+    pub fn percentiles(&self) -> Option<RatePercentiles> {
+        self.histogram.percentiles()
+    }
+}
+
+/// Distribution of per-interval generation rates, showing the variance an average
+/// hides, e.g. from thermal throttling or a stalled sink
+pub struct RateHistogram {
+    histogram: Histogram<u64>,
+}
+
+impl RateHistogram {
+    pub fn new() -> Self {
+        Self {
+            // Three significant figures; the histogram grows to whatever rates it sees
+            histogram: Histogram::new(3).expect("3 significant figures are supported"),
+        }
+    }
+
+    /// Record one rate sample in wallets per second
+    pub fn record(&mut self, wallets_per_second: f64) {
+        // Only fails if even a resized histogram can't hold the rate, which no real run reaches
+        let _ = self.histogram.record(wallets_per_second.round() as u64);
+    }
+
+    /// The median, 90th and 99th percentile rates, `None` before the first sample
+    ///
+    /// ```
+    /// use solana_vanity_wallet::stats::RateHistogram;
+    ///
+    /// let mut histogram = RateHistogram::new();
+    /// assert!(histogram.percentiles().is_none());
+    /// // Small enough to be recorded exactly; larger rates are kept to 3 significant figures
+    /// for rate in 1..=100 {
+    ///     histogram.record(rate as f64 * 10.0);
+    /// }
+    /// let percentiles = histogram.percentiles().unwrap();
+    /// assert_eq!((percentiles.p50, percentiles.p90, percentiles.p99), (500, 900, 990));
+    /// assert_eq!(
+    ///     percentiles.to_string(),
+    ///     "p50 500, p90 900, p99 990 wallets/second (100 samples)"
+    /// );
+    ///
+    /// let mut histogram = RateHistogram::new();
+    /// for rate in 1..=100 {
+    ///     histogram.record(rate as f64 * 100_000.0);
+    /// }
+    /// let p99 = histogram.percentiles().unwrap().p99 as f64;
+    /// assert!((p99 / 9_900_000.0 - 1.0).abs() < 0.001);
+    /// ```
+    pub fn percentiles(&self) -> Option<RatePercentiles> {
+        if self.histogram.is_empty() {
+            return None;
+        }
+        Some(RatePercentiles {
+            p50: self.histogram.value_at_quantile(0.5),
+            p90: self.histogram.value_at_quantile(0.9),
+            p99: self.histogram.value_at_quantile(0.99),
+            samples: self.histogram.len(),
+        })
+    }
+}
+
+impl Default for RateHistogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Percentiles of the sampled generation rate, in wallets per second
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct RatePercentiles {
+    pub p50: u64,
+    pub p90: u64,
+    pub p99: u64,
+    /// How many rate samples they were taken from
+    pub samples: u64,
+}
+
+impl fmt::Display for RatePercentiles {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "p50 {}, p90 {}, p99 {} wallets/second ({} samples)",
+            self.p50, self.p90, self.p99, self.samples
+        )
+    }
+}
+
+/// How many wallets were generated per vanity wallet found ("1 in N"),
+/// or `None` while nothing has been found yet
+pub fn success_rate(generated: u64, found: u64) -> Option<u64> {
+    generated.checked_div(found)
+}
+
+/// Claim the next slot of the `found` counter, returning the new total, or `None` once
+/// `limit` finds have been claimed. The check and the increment are one
+/// compare-and-swap, so threads racing for the last slot can't both win and overshoot
+/// the limit the way a load followed by `fetch_add` can.
+///
+/// ```
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+/// use solana_vanity_wallet::stats::claim_find;
+///
+/// let found = AtomicUsize::new(0);
+/// let claimed = AtomicUsize::new(0);
+/// std::thread::scope(|scope| {
+///     for _ in 0..16 {
+///         scope.spawn(|| {
+///             // Every attempt "matches", so all threads race for the same 3 slots
+///             for _ in 0..1_000 {
+///                 if claim_find(&found, Some(3)).is_some() {
+///                     claimed.fetch_add(1, Ordering::Relaxed);
+///                 }
+///             }
+///         });
+///     }
+/// });
+/// assert_eq!(claimed.into_inner(), 3);
+/// assert_eq!(found.into_inner(), 3);
+/// ```
+///
+/// A `--count` run stops as the last slot is claimed, and workers check for the stop
+/// before every attempt, so the final counters are consistent: each worker makes at
+/// most the one attempt it had started after the target was hit.
+///
+/// ```
+/// use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+/// use solana_vanity_wallet::stats::claim_find;
+///
+/// let threads = 8;
+/// let generated = AtomicUsize::new(0);
+/// let found = AtomicUsize::new(0);
+/// let stop = AtomicBool::new(false);
+/// let generated_at_target = AtomicUsize::new(0);
+/// std::thread::scope(|scope| {
+///     for _ in 0..threads {
+///         scope.spawn(|| {
+///             for attempt in 0.. {
+///                 if stop.load(Ordering::Acquire) {
+///                     break;
+///                 }
+///                 generated.fetch_add(1, Ordering::Relaxed);
+///                 // Every 100th attempt matches
+///                 if attempt % 100 == 0 && claim_find(&found, Some(50)) == Some(50) {
+///                     stop.store(true, Ordering::Release);
+///                     let at_target = generated.load(Ordering::Relaxed);
+///                     generated_at_target.store(at_target, Ordering::Relaxed);
+///                 }
+///             }
+///         });
+///     }
+/// });
+/// assert_eq!(found.into_inner(), 50);
+/// let stragglers = generated.into_inner() - generated_at_target.into_inner();
+/// assert!(stragglers < threads);
+/// ```
+pub fn claim_find(found: &AtomicUsize, limit: Option<u64>) -> Option<usize> {
+    // Only the count itself is shared, so Relaxed is enough for the swap to be exact
+    found
+        .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |total| {
+            limit
+                .is_none_or(|limit| (total as u64) < limit)
+                .then_some(total + 1)
+        })
+        .ok()
+        .map(|total| total + 1)
+}
+
+/// Attempts after which a first match has been seen with probability `quantile`,
+/// when each attempt matches with probability `probability`. Attempts until the first
+/// match follow a geometric distribution, whose quantiles are ln(1 - q) / ln(1 - p).
+/// The median is only about 69% of the mean (1 / p), and the 99th percentile is 4.6 times it.
+///
+/// ```
+/// use solana_vanity_wallet::stats::attempts_quantile;
+///
+/// let mean = 58.0 * 58.0;
+/// let median = attempts_quantile(1.0 / mean, 0.5);
+/// assert!((median / mean - std::f64::consts::LN_2).abs() < 0.001);
+/// assert!(attempts_quantile(1.0 / mean, 0.99) > 4.6 * mean);
+/// ```
+pub fn attempts_quantile(probability: f64, quantile: f64) -> f64 {
+    (-quantile).ln_1p() / (-probability).ln_1p()
+}
+
+/// Seconds until `target` wallets are found, extrapolating the average find rate so far.
+/// `None` while nothing has been found, as there is no rate to go on yet.
+///
+/// ```
+/// use solana_vanity_wallet::stats::eta_secs;
+///
+/// // 10 finds in 100 seconds is one every 10 seconds, so 40 more take 400
+/// assert_eq!(eta_secs(10, 50, 100.0), Some(400.0));
+/// assert_eq!(eta_secs(50, 50, 100.0), Some(0.0));
+/// assert_eq!(eta_secs(0, 50, 100.0), None);
+/// ```
+pub fn eta_secs(found: u64, target: u64, elapsed_secs: f64) -> Option<f64> {
+    let finds_per_second = found as f64 / elapsed_secs;
+    (found > 0 && finds_per_second.is_finite())
+        .then(|| target.saturating_sub(found) as f64 / finds_per_second)
+}
+
+/// Format a number of seconds with the largest unit that keeps it above 1, e.g. "3.2 hours"
+pub fn format_duration(secs: f64) -> String {
+    const UNITS: [(&str, f64); 5] = [
+        ("years", 365.0 * 86_400.0),
+        ("days", 86_400.0),
+        ("hours", 3_600.0),
+        ("minutes", 60.0),
+        ("seconds", 1.0),
+    ];
+
+    let (unit, size) = UNITS
+        .iter()
+        .find(|(_, size)| secs >= *size)
+        .unwrap_or(&UNITS[UNITS.len() - 1]);
+    format!("{:.1} {}", secs / size, unit)
+}
+
+/// How many recent finds the dashboard and the TUI list
+pub const RECENT_FINDS: usize = 20;
+
+/// A found wallet as shown on the dashboard and the TUI. Private keys never leave the process.
+#[derive(Clone, Debug, Serialize)]
+pub struct RecentFind {
+    pub public_key: String,
+    pub matched_pattern: String,
+    pub found_at: DateTime<Utc>,
+}
+
+/// Statistics of a running search, for the dashboard and the TUI
+#[derive(Debug, Serialize)]
+pub struct LiveStats {
+    pub elapsed_secs: f64,
+    pub total_generated: u64,
+    pub total_found: u64,
+    pub found_by_pattern: BTreeMap<String, u64>,
+    /// Smoothed rate from the last progress update, or the average before the first one
+    pub wallets_per_second: f64,
+    /// Newest first
+    pub recent_finds: Vec<RecentFind>,
+    /// Whether the workers are paused
+    pub paused: bool,
+}
+
+/// Final statistics of a run, written as JSON on exit
+#[derive(Debug, Serialize)]
+pub struct RunSummary {
+    pub started_at: DateTime<Utc>,
+    pub ended_at: DateTime<Utc>,
+    pub elapsed_secs: f64,
+    /// Wallets generated until the stop; after a `--count` stop, each worker adds at
+    /// most the one it was generating when the last find was claimed
+    pub total_generated: u64,
+    pub total_found: u64,
+    pub found_by_pattern: BTreeMap<String, u64>,
+    pub wallets_per_second: f64,
+    pub threads: usize,
+    pub patterns: Vec<String>,
+    /// MongoDB collection the wallets were saved to, when MongoDB was the sink
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub collection_name: Option<String>,
+    /// The match covering the most address characters, if anything was found
+    pub rarest_find: Option<RarestFind>,
+    /// What ended the run
+    pub stop_reason: Option<StopReason>,
+    /// With `--no-persist-benchmark`, how many wallets reached the in-memory sink
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kept_in_memory: Option<usize>,
+    /// With a dropping `--writer-full-policy`, how many found wallets were never saved
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dropped_by_writer_queue: Option<u64>,
+    /// Spread of the rate between progress updates; `None` if the run ended before the first
+    pub rate_percentiles: Option<RatePercentiles>,
+}
+
+/// Why a run stopped
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StopReason {
+    /// Ctrl+C, SIGTERM or quitting the TUI
+    Signal,
+    /// `--max-runtime` elapsed
+    Time,
+    /// `--count` wallets were found
+    Count,
+    /// `--progressive` found an address ending with the whole target
+    Target,
+}
+
+/// The longest match of a run
+#[derive(Clone, Debug, Serialize)]
+pub struct RarestFind {
+    pub public_key: String,
+    pub matched_pattern: String,
+    /// Address characters the match pinned down
+    pub matched_len: usize,
+}
+
+/// Write per-pattern find counts as a JSON object to `path`. The counts go to a
+/// temporary file next to it that is then renamed over it, so readers polling the
+/// file never see a partial write.
+///
+/// ```
+/// use std::collections::BTreeMap;
+/// use solana_vanity_wallet::stats::write_pattern_counts;
+///
+/// let path = std::env::temp_dir().join(format!("tally-{}.json", std::process::id()));
+/// let counts = BTreeMap::from([("pump".to_string(), 3), ("moon".to_string(), 1)]);
+/// write_pattern_counts(&path, &counts).unwrap();
+///
+/// let read: BTreeMap<String, u64> =
+///     serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+/// assert_eq!(read, counts);
+/// std::fs::remove_file(path).unwrap();
+/// ```
+pub fn write_pattern_counts(path: &Path, counts: &BTreeMap<String, u64>) -> Result<()> {
+    let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+    temp_name.push(".tmp");
+    let temp_path = path.with_file_name(temp_name);
+
+    let mut writer = BufWriter::new(File::create(&temp_path)?);
+    serde_json::to_writer(&mut writer, counts)?;
+    writer.write_all(b"\n")?;
+    writer.flush()?;
+    drop(writer);
+    std::fs::rename(&temp_path, path)?;
+    Ok(())
+}
+
+impl RunSummary {
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        writer.write_all(b"\n")?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// How long a throttle window lasts before the rate is measured afresh
+const THROTTLE_WINDOW: Duration = Duration::from_secs(1);
+
+/// Caps the combined generation rate by telling workers how long to pause
+pub struct Throttle {
+    max_rate: f64,
+    /// Start of the current window and the total count at that moment
+    window: Mutex<(Instant, usize)>,
+}
+
+impl Throttle {
+    pub fn new(max_rate: f64) -> Self {
+        Self {
+            max_rate,
+            window: Mutex::new((Instant::now(), 0)),
+        }
+    }
+
+    /// Given the total number of attempts so far, how long to pause to stay under the cap
+    pub fn delay(&self, count: usize) -> Option<Duration> {
+        let mut window = self.window.lock().unwrap();
+        let (started, start_count) = *window;
+        let elapsed = started.elapsed();
+        let allowed = count.saturating_sub(start_count) as f64 / self.max_rate;
+        let ahead = allowed - elapsed.as_secs_f64();
+
+        // Only start a new window once caught up, so pauses can't be skipped
+        if ahead <= 0.0 && elapsed >= THROTTLE_WINDOW {
+            *window = (Instant::now(), count);
+        }
+        (ahead > 0.0).then(|| Duration::from_secs_f64(ahead))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn run_summary_serializes_every_field() {
+        let summary = RunSummary {
+            started_at: "2024-01-01T00:00:00Z".parse().unwrap(),
+            ended_at: "2024-01-01T00:01:00Z".parse().unwrap(),
+            elapsed_secs: 60.0,
+            total_generated: 1_200_000,
+            total_found: 2,
+            found_by_pattern: BTreeMap::from([("pump".to_string(), 2), ("moon".to_string(), 0)]),
+            wallets_per_second: 20_000.0,
+            threads: 4,
+            patterns: vec!["pump".to_string(), "moon".to_string()],
+            collection_name: None,
+            rarest_find: None,
+            stop_reason: Some(StopReason::Signal),
+            kept_in_memory: None,
+            dropped_by_writer_queue: None,
+            rate_percentiles: None,
+        };
+
+        let json = serde_json::to_value(&summary).unwrap();
+        let keys = |json: &serde_json::Value| {
+            json.as_object()
+                .unwrap()
+                .keys()
+                .cloned()
+                .collect::<std::collections::BTreeSet<_>>()
+        };
+        let expected = [
+            "started_at",
+            "ended_at",
+            "elapsed_secs",
+            "total_generated",
+            "total_found",
+            "found_by_pattern",
+            "wallets_per_second",
+            "threads",
+            "patterns",
+            "rarest_find",
+            "stop_reason",
+            "rate_percentiles",
+        ];
+        assert_eq!(keys(&json), expected.map(String::from).into());
+
+        assert_eq!(json["started_at"], "2024-01-01T00:00:00Z");
+        assert_eq!(json["elapsed_secs"], 60.0);
+        assert_eq!(json["total_generated"], 1_200_000);
+        assert_eq!(json["found_by_pattern"]["pump"], 2);
+        assert_eq!(json["found_by_pattern"]["moon"], 0);
+        assert_eq!(json["threads"], 4);
+        assert_eq!(json["patterns"], serde_json::json!(["pump", "moon"]));
+        assert_eq!(json["stop_reason"], "signal");
+        // The mode-specific fields only appear when they apply
+        let summary = RunSummary {
+            collection_name: Some("wallets".to_string()),
+            kept_in_memory: Some(2),
+            dropped_by_writer_queue: Some(0),
+            ..summary
+        };
+        let json = serde_json::to_value(&summary).unwrap();
+        for key in [
+            "collection_name",
+            "kept_in_memory",
+            "dropped_by_writer_queue",
+        ] {
+            assert!(keys(&json).contains(key), "{key}");
+        }
+    }
+}
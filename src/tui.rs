@@ -0,0 +1,174 @@
+use crate::error::Result;
+use crate::stats::{self, LiveStats};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Style, Stylize};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, List, ListItem, Paragraph, Sparkline};
+use ratatui::{DefaultTerminal, Frame};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// How long to wait for a key press before redrawing
+const REDRAW_INTERVAL: Duration = Duration::from_millis(250);
+
+/// One sparkline bar covers this long
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Samples kept for the sparkline; wider terminals just show the most recent ones
+const MAX_SAMPLES: usize = 300;
+
+/// What the TUI shows and steers, provided by the running search
+pub trait TuiControl {
+    fn stats(&self) -> LiveStats;
+
+    /// Pause the workers, or resume them if they are paused
+    fn toggle_pause(&self);
+
+    /// Start the graceful shutdown, as Ctrl+C does without the TUI
+    fn quit(&self);
+
+    /// Whether the run is stopping for another reason, e.g. `--max-runtime`
+    fn stopping(&self) -> bool;
+}
+
+/// Wallets generated per [`SAMPLE_INTERVAL`], newest last
+struct RateHistory {
+    samples: VecDeque<u64>,
+    last_total: u64,
+    last_sample: Instant,
+}
+
+impl RateHistory {
+    fn new() -> Self {
+        Self {
+            samples: VecDeque::new(),
+            last_total: 0,
+            last_sample: Instant::now(),
+        }
+    }
+
+    fn update(&mut self, total_generated: u64) {
+        if self.last_sample.elapsed() < SAMPLE_INTERVAL {
+            return;
+        }
+        self.samples
+            .push_back(total_generated.saturating_sub(self.last_total));
+        if self.samples.len() > MAX_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.last_total = total_generated;
+        self.last_sample = Instant::now();
+    }
+
+    /// The newest `count` samples, oldest first
+    fn recent(&self, count: usize) -> Vec<u64> {
+        let skip = self.samples.len().saturating_sub(count);
+        self.samples.iter().skip(skip).copied().collect()
+    }
+}
+
+/// Take over the terminal and show live statistics until the user quits or the run stops.
+/// `p` pauses and resumes the search; `q`, `Esc` or Ctrl+C quit. The terminal is
+/// restored before returning, even on errors.
+pub fn run(control: &dyn TuiControl) -> Result<()> {
+    let mut terminal = ratatui::try_init()?;
+    let result = event_loop(&mut terminal, control);
+    ratatui::try_restore()?;
+    result
+}
+
+fn event_loop(terminal: &mut DefaultTerminal, control: &dyn TuiControl) -> Result<()> {
+    let mut history = RateHistory::new();
+    while !control.stopping() {
+        let stats = control.stats();
+        history.update(stats.total_generated);
+        terminal.draw(|frame| draw(frame, &stats, &history))?;
+
+        if !event::poll(REDRAW_INTERVAL)? {
+            continue;
+        }
+        // Raw mode swallows Ctrl+C, so it arrives here as a key press instead of SIGINT
+        if let Event::Key(key) = event::read()? {
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            match key.code {
+                KeyCode::Char('p') => control.toggle_pause(),
+                KeyCode::Char('q') | KeyCode::Esc => control.quit(),
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    control.quit()
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(())
+}
+
+fn draw(frame: &mut Frame, stats: &LiveStats, history: &RateHistory) {
+    let [summary, sparkline, lists, help] = Layout::vertical([
+        Constraint::Length(3),
+        Constraint::Length(8),
+        Constraint::Min(5),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+    let [patterns, finds] =
+        Layout::horizontal([Constraint::Percentage(30), Constraint::Percentage(70)]).areas(lists);
+
+    let status = if stats.paused {
+        "PAUSED".yellow().bold()
+    } else {
+        "RUNNING".green().bold()
+    };
+    let summary_line = Line::from(vec![
+        status,
+        format!(
+            "  elapsed {}  generated {}  found {}  ~{:.0} wallets/s",
+            stats::format_duration(stats.elapsed_secs),
+            stats.total_generated,
+            stats.total_found,
+            stats.wallets_per_second
+        )
+        .into(),
+    ]);
+    frame.render_widget(
+        Paragraph::new(summary_line).block(Block::bordered().title(" Solana Vanity Wallet ")),
+        summary,
+    );
+
+    // Two columns go to the borders
+    let samples = history.recent(sparkline.width.saturating_sub(2) as usize);
+    frame.render_widget(
+        Sparkline::default()
+            .block(Block::bordered().title(" Wallets generated per second "))
+            .data(&samples)
+            .style(Style::default().fg(Color::Cyan)),
+        sparkline,
+    );
+
+    let pattern_items = stats
+        .found_by_pattern
+        .iter()
+        .map(|(pattern, found)| ListItem::new(format!("{:<12} {}", pattern, found)));
+    frame.render_widget(
+        List::new(pattern_items).block(Block::bordered().title(" Found by pattern ")),
+        patterns,
+    );
+
+    let find_items = stats.recent_finds.iter().map(|find| {
+        ListItem::new(format!(
+            "{}  {}  '{}'",
+            find.found_at.format("%H:%M:%S"),
+            find.public_key,
+            find.matched_pattern
+        ))
+    });
+    frame.render_widget(
+        List::new(find_items).block(Block::bordered().title(" Recent finds ")),
+        finds,
+    );
+
+    frame.render_widget(Paragraph::new(" p pause/resume   q quit".dark_gray()), help);
+}
@@ -1,38 +1,430 @@
+use crate::matcher::{MatchConfig, MatchTarget, PatternMatch};
+use bip39::{Language, Mnemonic, MnemonicType, Seed};
+use solana_sdk::derivation_path::DerivationPath;
+use solana_sdk::pubkey::Pubkey;
 use solana_sdk::signature::{Keypair, Signer};
+use solana_sdk::signer::keypair::keypair_from_seed_and_derivation_path;
+use std::sync::Arc;
+use zeroize::Zeroizing;
 
-#[derive(Clone)]
-pub struct WalletGenerator {}
+/// The SPL Token program, which owns classic token accounts
+pub const TOKEN_PROGRAM_ID: Pubkey =
+    solana_sdk::pubkey!("TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA");
 
-impl WalletGenerator {
-    pub fn new(_suffix: &str) -> Self {
-        // We ignore the suffix parameter since we hardcode "pump" in is_vanity_wallet
-        Self {}
+/// The SPL Associated Token Account program token accounts are derived under
+pub const ASSOCIATED_TOKEN_PROGRAM_ID: Pubkey =
+    solana_sdk::pubkey!("ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL");
+
+/// The associated token account of `owner` for `mint`, derived like
+/// `spl_associated_token_account::get_associated_token_address`: the program address
+/// for the seeds `[owner, TOKEN_PROGRAM_ID, mint]` under [`ASSOCIATED_TOKEN_PROGRAM_ID`]
+pub fn associated_token_address(owner: &Pubkey, mint: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[owner.as_ref(), TOKEN_PROGRAM_ID.as_ref(), mint.as_ref()],
+        &ASSOCIATED_TOKEN_PROGRAM_ID,
+    )
+    .0
+}
+
+/// How to recover a wallet that was derived from a BIP39 mnemonic
+#[derive(Clone, Debug)]
+pub struct MnemonicRecovery {
+    pub mnemonic: Arc<str>,
+    pub derivation_path: String,
+}
+
+/// A generated keypair, plus its recovery info in mnemonic mode
+pub struct GeneratedWallet {
+    pub keypair: Keypair,
+    /// The base58 public key, encoded once and reused for matching, logging and saving
+    pub pubkey: String,
+    pub recovery: Option<MnemonicRecovery>,
+    /// With [`WalletGenerator::with_ata_mint`], the base58 associated token account the
+    /// pattern is matched against instead of `pubkey`
+    pub associated_token_account: Option<String>,
+    /// With [`MatchTarget::PrivateKey`], the base58 private key the pattern is matched against
+    pub private_key: Option<Zeroizing<String>>,
+}
+
+impl GeneratedWallet {
+    fn new(keypair: Keypair, recovery: Option<MnemonicRecovery>) -> Self {
+        Self {
+            pubkey: WalletGenerator::get_pubkey_string(&keypair),
+            keypair,
+            recovery,
+            associated_token_account: None,
+            private_key: None,
+        }
     }
 
-    /// Generate a new random Solana keypair
-    pub fn generate_wallet(&self) -> Keypair {
-        let keypair = Keypair::new();
-        log::trace!(
-            "Generated new keypair with public key: {}",
-            keypair.pubkey()
+    /// The address, or private key, the pattern is matched against
+    pub fn matched_address(&self) -> &str {
+        self.private_key
+            .as_deref()
+            .map(String::as_str)
+            .or(self.associated_token_account.as_deref())
+            .unwrap_or(&self.pubkey)
+    }
+}
+
+/// A generated wallet that matched, and how it matched
+pub struct FoundWallet<'a> {
+    pub wallet: GeneratedWallet,
+    pub found: PatternMatch<'a>,
+}
+
+/// Where raw (non-mnemonic) keypairs come from
+pub trait KeypairSource: Send + Sync {
+    /// Produce the next candidate keypair
+    fn next(&mut self) -> Keypair;
+
+    /// A source for another worker, used when the generator is cloned
+    fn fork(&self) -> Box<dyn KeypairSource>;
+}
+
+/// Fresh keypairs from the operating system's RNG; the default source
+pub struct RandomKeypairs;
+
+impl KeypairSource for RandomKeypairs {
+    fn next(&mut self) -> Keypair {
+        Keypair::new()
+    }
+
+    fn fork(&self) -> Box<dyn KeypairSource> {
+        Box::new(RandomKeypairs)
+    }
+}
+
+/// Yields a fixed sequence of keypairs over and over, so a whole run can be
+/// reproduced, e.g. by injecting a key known to end with the searched suffix
+pub struct SequenceKeypairs {
+    keypairs: Vec<Keypair>,
+    next: usize,
+}
+
+impl SequenceKeypairs {
+    /// Panics if `keypairs` is empty
+    pub fn new(keypairs: Vec<Keypair>) -> Self {
+        assert!(
+            !keypairs.is_empty(),
+            "a keypair sequence needs at least one keypair"
         );
-        keypair
+        Self { keypairs, next: 0 }
+    }
+}
+
+impl KeypairSource for SequenceKeypairs {
+    fn next(&mut self) -> Keypair {
+        let keypair = &self.keypairs[self.next];
+        self.next = (self.next + 1) % self.keypairs.len();
+        copy_keypair(keypair)
+    }
+
+    /// Forks restart the sequence, so every worker sees the same keypairs
+    fn fork(&self) -> Box<dyn KeypairSource> {
+        Box::new(Self::new(self.keypairs.iter().map(copy_keypair).collect()))
     }
+}
+
+fn copy_keypair(keypair: &Keypair) -> Keypair {
+    let bytes = Zeroizing::new(keypair.to_bytes());
+    Keypair::from_bytes(bytes.as_ref()).expect("bytes of a valid keypair")
+}
+
+/// The seed currently being scanned in mnemonic mode
+struct MnemonicScan {
+    mnemonic: Arc<str>,
+    seed: Zeroizing<Vec<u8>>,
+    next_account: u32,
+}
 
-    /// Check if the wallet address ends with the specified suffix
-    /// Only matches exact case (lowercase "pump")
+pub struct WalletGenerator {
+    match_config: MatchConfig,
+    /// Number of BIP44 account indices derived from each mnemonic, if in mnemonic mode
+    derivation_scan: Option<u32>,
+    /// Only accept public keys that are valid ed25519 points, i.e. usable for signing
+    require_on_curve: bool,
+    /// Source of raw keypairs; unused in mnemonic mode
+    source: Box<dyn KeypairSource>,
+    scan: Option<MnemonicScan>,
+    /// Match each keypair's associated token account for this mint instead of its pubkey
+    ata_mint: Option<Pubkey>,
+    /// Log non-matching wallets at most this many characters short of a suffix
+    near_misses: Option<usize>,
+    /// In mnemonic mode, move on to a new seed once one of its accounts matched
+    group_by_seed: bool,
+}
+
+// Cloning starts a fresh scan so two workers never derive from the same seed
+impl Clone for WalletGenerator {
+    fn clone(&self) -> Self {
+        Self {
+            match_config: self.match_config.clone(),
+            derivation_scan: self.derivation_scan,
+            require_on_curve: self.require_on_curve,
+            source: self.source.fork(),
+            scan: None,
+            ata_mint: self.ata_mint,
+            near_misses: self.near_misses,
+            group_by_seed: self.group_by_seed,
+        }
+    }
+}
+
+impl WalletGenerator {
+    pub fn new(match_config: MatchConfig) -> Self {
+        Self {
+            match_config,
+            derivation_scan: None,
+            require_on_curve: true,
+            source: Box::new(RandomKeypairs),
+            scan: None,
+            ata_mint: None,
+            near_misses: None,
+            group_by_seed: false,
+        }
+    }
+
+    /// Derive wallets from random 12-word mnemonics instead of raw random keys,
+    /// scanning `m/44'/501'/0'/0'` through `m/44'/501'/{accounts - 1}'/0'` of each seed
+    pub fn with_mnemonic(mut self, accounts: u32) -> Self {
+        self.derivation_scan = Some(accounts.max(1));
+        self
+    }
+
+    /// Choose whether matches must be on the ed25519 curve. Keypairs always are,
+    /// so this only matters for searches over off-curve addresses such as PDAs.
+    pub fn with_require_on_curve(mut self, require_on_curve: bool) -> Self {
+        self.require_on_curve = require_on_curve;
+        self
+    }
+
+    /// Draw raw keypairs from `source` instead of the RNG, e.g. a
+    /// [`SequenceKeypairs`] for deterministic runs
+    pub fn with_keypair_source(mut self, source: Box<dyn KeypairSource>) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// Treat each keypair as a token account owner and match the pattern against its
+    /// associated token account for `mint` (see [`associated_token_address`]). Deriving
+    /// the address takes a few hashes per keypair, so this is slower than a plain search.
+    pub fn with_ata_mint(mut self, mint: Pubkey) -> Self {
+        self.ata_mint = Some(mint);
+        self
+    }
+
+    /// Log (at debug level) wallets that miss a suffix by at most `max_short` of its
+    /// leading characters, see [`MatchConfig::near_miss`]. Adds a comparison to every miss.
+    pub fn with_near_misses(mut self, max_short: usize) -> Self {
+        self.near_misses = Some(max_short);
+        self
+    }
+
+    /// In mnemonic mode, stop scanning a seed at its first matching account, so each
+    /// seed is found at most once and can be saved whole with [`Self::seed_accounts`]
+    pub fn with_group_by_seed(mut self) -> Self {
+        self.group_by_seed = true;
+        self
+    }
+
+    /// The patterns wallets are being searched for
+    pub fn patterns(&self) -> Vec<&str> {
+        self.match_config.patterns()
+    }
+
+    /// Roughly how many keys have to be generated per match, see [`MatchConfig::estimated_attempts`]
+    pub fn estimated_attempts(&self) -> Option<f64> {
+        self.match_config.estimated_attempts()
+    }
+
+    /// Generate the next candidate wallet
+    pub fn generate_wallet(&mut self) -> GeneratedWallet {
+        let mut wallet = match self.derivation_scan {
+            Some(accounts) => self.next_derived_wallet(accounts),
+            None => GeneratedWallet::new(self.source.next(), None),
+        };
+        if let Some(mint) = &self.ata_mint {
+            let owner = wallet.keypair.pubkey();
+            wallet.associated_token_account =
+                Some(associated_token_address(&owner, mint).to_string());
+        }
+        if self.match_config.target == MatchTarget::PrivateKey {
+            // Encoding the 64-byte keypair costs about twice the public key's encoding
+            wallet.private_key = Some(Self::get_private_key_string(&wallet.keypair));
+        }
+        log::trace!("Generated new keypair with public key: {}", wallet.pubkey);
+        wallet
+    }
+
+    /// Derive the next account of the current mnemonic, starting a new one when exhausted.
+    /// Seed generation (PBKDF2) dominates the cost, so scanning more accounts per seed is cheaper.
+    fn next_derived_wallet(&mut self, accounts: u32) -> GeneratedWallet {
+        let scan = match &mut self.scan {
+            Some(scan) if scan.next_account < accounts => scan,
+            scan => {
+                let mnemonic = Mnemonic::new(MnemonicType::Words12, Language::English);
+                let seed = Zeroizing::new(Seed::new(&mnemonic, "").as_bytes().to_vec());
+                scan.insert(MnemonicScan {
+                    mnemonic: mnemonic.phrase().into(),
+                    seed,
+                    next_account: 0,
+                })
+            }
+        };
+
+        let account = scan.next_account;
+        scan.next_account += 1;
+        Self::derive_account(&scan.seed, &scan.mnemonic, account)
+    }
+
+    fn derive_account(seed: &[u8], mnemonic: &Arc<str>, account: u32) -> GeneratedWallet {
+        let derivation_path = DerivationPath::new_bip44(Some(account), Some(0));
+        let path_string = format!("m/44'/501'/{}'/0'", account);
+        let keypair = keypair_from_seed_and_derivation_path(seed, Some(derivation_path))
+            .expect("a 64-byte BIP39 seed always derives a valid keypair");
+
+        GeneratedWallet::new(
+            keypair,
+            Some(MnemonicRecovery {
+                mnemonic: mnemonic.clone(),
+                derivation_path: path_string,
+            }),
+        )
+    }
+
+    /// Derive the first `accounts` accounts of a mnemonic found in mnemonic mode, i.e.
+    /// every wallet [`Self::with_mnemonic`] scans for that seed. Redoes the seed
+    /// derivation, so it's meant for matches only.
+    ///
+    /// ```
+    /// use solana_vanity_wallet::matcher::MatchConfig;
+    /// use solana_vanity_wallet::wallet_generator::WalletGenerator;
+    ///
+    /// let mut generator = WalletGenerator::new(MatchConfig::new(&[])).with_mnemonic(3);
+    /// let scanned: Vec<_> = (0..3).map(|_| generator.generate_wallet()).collect();
+    /// let mnemonic = &scanned[0].recovery.as_ref().unwrap().mnemonic;
+    ///
+    /// let accounts = WalletGenerator::seed_accounts(mnemonic, 3);
+    /// for (index, (account, wallet)) in accounts.iter().zip(&scanned).enumerate() {
+    ///     let path = &account.recovery.as_ref().unwrap().derivation_path;
+    ///     assert_eq!(path, &format!("m/44'/501'/{}'/0'", index));
+    ///     assert_eq!(account.pubkey, wallet.pubkey);
+    /// }
+    /// ```
+    pub fn seed_accounts(mnemonic: &Arc<str>, accounts: u32) -> Vec<GeneratedWallet> {
+        let phrase = Mnemonic::from_phrase(mnemonic, Language::English)
+            .expect("mnemonics come from the generator");
+        let seed = Zeroizing::new(Seed::new(&phrase, "").as_bytes().to_vec());
+        (0..accounts)
+            .map(|account| Self::derive_account(&seed, mnemonic, account))
+            .collect()
+    }
+
+    /// Check if the wallet address matches the configured pattern
+    /// and passes the filters. Case is exact unless `--smart-case` is on.
+    ///
+    /// This only encodes the public key; the matching itself lives in
+    /// [`MatchConfig::matches`], which works on plain strings.
     pub fn is_vanity_wallet(&self, keypair: &Keypair) -> bool {
-        let pubkey = keypair.pubkey().to_string();
-        // Check if the public key ends with exactly "pump" (no case conversion)
-        let is_vanity = pubkey.ends_with("pump");
+        let address = match (&self.ata_mint, self.match_config.target) {
+            (_, MatchTarget::PrivateKey) => Self::get_private_key_string(keypair),
+            (Some(mint), _) => {
+                Zeroizing::new(associated_token_address(&keypair.pubkey(), mint).to_string())
+            }
+            (None, _) => Zeroizing::new(Self::get_pubkey_string(keypair)),
+        };
+        self.match_pubkey(&address, keypair).is_some()
+    }
 
-        if is_vanity {
-            log::info!("Found vanity wallet ending with 'pump': {}", pubkey);
-        } else {
-            log::trace!("Public key {} does not end with 'pump'", pubkey);
+    /// How a generated wallet matched, if it did. Uses the already encoded address.
+    pub fn find_match(&self, wallet: &GeneratedWallet) -> Option<PatternMatch<'_>> {
+        self.match_pubkey(wallet.matched_address(), &wallet.keypair)
+    }
+
+    /// Generate one wallet and check it: the whole search step, without counting,
+    /// logging or saving. With a [`SequenceKeypairs`] source the outcome is deterministic.
+    ///
+    /// ```
+    /// use solana_sdk::signature::{Keypair, Signer};
+    /// use solana_vanity_wallet::matcher::MatchConfig;
+    /// use solana_vanity_wallet::wallet_generator::{SequenceKeypairs, WalletGenerator};
+    ///
+    /// let keypair = Keypair::new();
+    /// let address = keypair.pubkey().to_string();
+    /// let suffix = address[address.len() - 3..].to_string();
+    /// let mut generator = WalletGenerator::new(MatchConfig::new(&[suffix.clone()]))
+    ///     .with_keypair_source(Box::new(SequenceKeypairs::new(vec![keypair])));
+    ///
+    /// let hit = generator.try_one().expect("the only keypair ends with the suffix");
+    /// assert_eq!(hit.wallet.pubkey, address);
+    /// assert_eq!(hit.found.pattern, suffix);
+    /// ```
+    ///
+    /// With [`MatchTarget::PrivateKey`] the base58 private key is matched instead:
+    ///
+    /// ```
+    /// use solana_sdk::signature::{Keypair, Signer};
+    /// use solana_vanity_wallet::matcher::{MatchConfig, MatchTarget};
+    /// use solana_vanity_wallet::wallet_generator::{SequenceKeypairs, WalletGenerator};
+    ///
+    /// let keypair = Keypair::new();
+    /// let private_key = WalletGenerator::get_private_key_string(&keypair);
+    /// let suffix = private_key[private_key.len() - 4..].to_string();
+    /// let config = MatchConfig {
+    ///     target: MatchTarget::PrivateKey,
+    ///     ..MatchConfig::new(&[suffix.clone()])
+    /// };
+    /// let keypairs = || Box::new(SequenceKeypairs::new(vec![keypair.insecure_clone()]));
+    ///
+    /// let mut generator = WalletGenerator::new(config).with_keypair_source(keypairs());
+    /// let hit = generator.try_one().expect("the private key ends with the suffix");
+    /// assert_eq!(hit.wallet.matched_address(), private_key.as_str());
+    /// assert_eq!(hit.wallet.pubkey, keypair.pubkey().to_string());
+    ///
+    /// // The same suffix is almost certainly not at the end of the public key
+    /// let mut generator =
+    ///     WalletGenerator::new(MatchConfig::new(&[suffix.clone()])).with_keypair_source(keypairs());
+    /// assert_eq!(
+    ///     generator.try_one().is_some(),
+    ///     keypair.pubkey().to_string().ends_with(&suffix)
+    /// );
+    /// ```
+    pub fn try_one(&mut self) -> Option<FoundWallet<'_>> {
+        let wallet = self.generate_wallet();
+        if self.find_match(&wallet).is_some() {
+            if self.group_by_seed {
+                self.scan = None;
+            }
+            // Matched again as the returned match borrows the generator; hits are rare
+            let found = self.find_match(&wallet).expect("the wallet just matched");
+            return Some(FoundWallet { wallet, found });
+        }
+
+        if let Some((suffix, short)) = self.near_misses.and_then(|max_short| {
+            self.match_config
+                .near_miss(wallet.matched_address(), max_short)
+        }) {
+            log::debug!(
+                "Near miss: {} is {} character(s) short of '{}'",
+                wallet.matched_address(),
+                short,
+                suffix
+            );
         }
+        None
+    }
+
+    fn match_pubkey(&self, pubkey: &str, keypair: &Keypair) -> Option<PatternMatch<'_>> {
+        let found = self.match_config.find_match(pubkey)?;
+        // The curve check decompresses the point, so only run it on string matches
+        (!self.require_on_curve || Self::is_on_curve(keypair)).then_some(found)
+    }
 
-        is_vanity
+    /// Check that the public key is a valid ed25519 point, meaning it has a
+    /// private key and can sign. Program derived addresses are deliberately off-curve.
+    pub fn is_on_curve(keypair: &Keypair) -> bool {
+        keypair.pubkey().is_on_curve()
     }
 
     /// Get the public key as a string
@@ -40,8 +432,48 @@ impl WalletGenerator {
         keypair.pubkey().to_string()
     }
 
-    /// Get the private key as a base58 string
-    pub fn get_private_key_string(keypair: &Keypair) -> String {
-        bs58::encode(keypair.to_bytes()).into_string()
+    /// Get the private key as a base58 string, wiped from memory when dropped
+    pub fn get_private_key_string(keypair: &Keypair) -> Zeroizing<String> {
+        let bytes = Zeroizing::new(keypair.to_bytes());
+        Zeroizing::new(bs58::encode(bytes.as_ref()).into_string())
+    }
+
+    /// Check that a stored base58 private key decodes to a keypair whose public key
+    /// is `public_key`; the error says what's wrong with it
+    pub fn verify_private_key(public_key: &str, private_key: &str) -> Result<(), &'static str> {
+        let bytes = Zeroizing::new(
+            bs58::decode(private_key)
+                .into_vec()
+                .map_err(|_| "private key is not valid base58")?,
+        );
+        // Rejects a public half that doesn't belong to the secret half, too
+        let keypair =
+            Keypair::from_bytes(&bytes).map_err(|_| "private key is not a valid keypair")?;
+        if keypair.pubkey().to_string() != public_key {
+            return Err("private key belongs to a different public key");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn associated_token_address_matches_a_known_vector() {
+        // The owner's USDC account, derived independently of solana-sdk from the
+        // seeds [owner, token program, mint] under the associated token program
+        let owner = solana_sdk::pubkey!("4Nd1mBQtrMJVYVfKf2PJy9NZUZdTAsp7D4xWLs4gDB4T");
+        let mint = solana_sdk::pubkey!("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v");
+        assert_eq!(
+            associated_token_address(&owner, &mint).to_string(),
+            "F8biqkCRK2tHR6EncrcXDGgVTkGRrtojqyW39w41Qspn"
+        );
+        // The seed order matters: swapping owner and mint gives another address
+        assert_ne!(
+            associated_token_address(&mint, &owner),
+            associated_token_address(&owner, &mint)
+        );
     }
 }
@@ -0,0 +1,506 @@
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::hook;
+use crate::sink::{read_wallet_file, FileSink, WalletDocument, WalletSink};
+use log::{error, info, warn};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::OpenOptions;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+
+/// Maximum number of attempts made to save a found wallet
+pub const MAX_SAVE_RETRIES: usize = 3;
+
+/// Backoff ceiling before the first retry; it doubles with every further retry
+const RETRY_BACKOFF_BASE_MS: u64 = 500;
+
+/// Upper bound on any single backoff
+const RETRY_BACKOFF_MAX_MS: u64 = 30_000;
+
+/// How long to wait before retry number `retry` (starting at 1): exponential backoff
+/// with full jitter, i.e. a random delay between zero and `base * 2^(retry - 1)`, so
+/// writers recovering from the same outage don't all retry in lockstep
+pub fn retry_backoff(retry: usize, rng: &mut impl Rng) -> std::time::Duration {
+    let exponent = retry.saturating_sub(1).min(16) as u32;
+    let ceiling = (RETRY_BACKOFF_BASE_MS << exponent).min(RETRY_BACKOFF_MAX_MS);
+    std::time::Duration::from_millis(rng.gen_range(0..=ceiling))
+}
+
+/// Default capacity of the queue between the workers and the writer task
+pub const WRITER_QUEUE_SIZE: usize = 1024;
+
+/// What a worker does with a found wallet when the writer's queue is full
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FullPolicy {
+    /// Wait for room, slowing generation down to the sink's pace; nothing is lost
+    #[default]
+    Block,
+    /// Drop the longest-queued wallet to make room for the new one
+    DropOldest,
+    /// Drop the new wallet and keep the queued ones
+    DropNewest,
+}
+
+struct QueueState {
+    wallets: VecDeque<WalletDocument>,
+    senders: usize,
+    receiver_alive: bool,
+}
+
+struct Queue {
+    state: Mutex<QueueState>,
+    capacity: usize,
+    policy: FullPolicy,
+    /// Wakes the writer when a wallet is queued or the last sender is dropped
+    pushed: Notify,
+    /// Wakes blocked workers when the writer takes a wallet or stops
+    popped: Notify,
+    dropped: AtomicU64,
+}
+
+/// Create the queue between the workers and the writer task, holding up to
+/// `capacity` wallets and applying `policy` once it's full. The receiver sees the
+/// end of the queue when every sender is dropped.
+///
+/// ```
+/// use solana_vanity_wallet::sink::WalletDocument;
+/// use solana_vanity_wallet::writer::{wallet_queue, FullPolicy};
+/// # fn wallet(public_key: &str) -> WalletDocument {
+/// #     serde_json::from_value(serde_json::json!({
+/// #         "public_key": public_key,
+/// #         "private_key": "",
+/// #         "created_at": "2024-01-01T00:00:00Z",
+/// #     }))
+/// #     .unwrap()
+/// # }
+///
+/// # tokio::runtime::Runtime::new().unwrap().block_on(async {
+/// // The writer isn't keeping up at all: nothing is taken until the workers are done
+/// for (policy, kept) in [(FullPolicy::DropNewest, ["1", "2"]), (FullPolicy::DropOldest, ["4", "5"])] {
+///     let (sender, mut receiver) = wallet_queue(2, policy);
+///     for key in ["1", "2", "3", "4", "5"] {
+///         sender.send(wallet(key)).await.unwrap();
+///     }
+///     assert_eq!(sender.dropped(), 3);
+///     drop(sender);
+///     let mut saved = Vec::new();
+///     while let Some(wallet) = receiver.recv().await {
+///         saved.push(wallet.public_key.clone());
+///     }
+///     assert_eq!(saved, kept);
+/// }
+///
+/// // Blocking waits for a slow writer instead, and every wallet arrives
+/// let (sender, mut receiver) = wallet_queue(2, FullPolicy::Block);
+/// let writer = tokio::spawn(async move {
+///     let mut saved = Vec::new();
+///     while let Some(wallet) = receiver.recv().await {
+///         tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+///         saved.push(wallet.public_key.clone());
+///     }
+///     saved
+/// });
+/// for key in ["1", "2", "3", "4", "5"] {
+///     sender.send(wallet(key)).await.unwrap();
+/// }
+/// assert_eq!(sender.dropped(), 0);
+/// drop(sender);
+/// assert_eq!(writer.await.unwrap(), ["1", "2", "3", "4", "5"]);
+/// # });
+/// ```
+pub fn wallet_queue(capacity: usize, policy: FullPolicy) -> (WalletSender, WalletReceiver) {
+    let queue = Arc::new(Queue {
+        state: Mutex::new(QueueState {
+            wallets: VecDeque::with_capacity(capacity),
+            senders: 1,
+            receiver_alive: true,
+        }),
+        capacity: capacity.max(1),
+        policy,
+        pushed: Notify::new(),
+        popped: Notify::new(),
+        dropped: AtomicU64::new(0),
+    });
+    (WalletSender(queue.clone()), WalletReceiver(queue))
+}
+
+/// The workers' end of [`wallet_queue`]
+pub struct WalletSender(Arc<Queue>);
+
+impl WalletSender {
+    /// Queue a found wallet, applying the full policy if there's no room. Fails,
+    /// handing the wallet back, if the writer task has stopped.
+    pub async fn send(&self, wallet: WalletDocument) -> std::result::Result<(), WalletDocument> {
+        loop {
+            // Registered before checking for room, so a wallet taken in between still wakes us
+            let popped = self.0.popped.notified();
+            tokio::pin!(popped);
+            popped.as_mut().enable();
+            {
+                let mut state = self.0.state.lock().unwrap();
+                if !state.receiver_alive {
+                    return Err(wallet);
+                }
+                if state.wallets.len() < self.0.capacity {
+                    state.wallets.push_back(wallet);
+                    drop(state);
+                    self.0.pushed.notify_one();
+                    return Ok(());
+                }
+                match self.0.policy {
+                    FullPolicy::Block => {}
+                    FullPolicy::DropNewest => {
+                        drop(state);
+                        self.0.dropped.fetch_add(1, Ordering::Relaxed);
+                        warn!(
+                            "Writer queue is full; dropped newly found wallet {}",
+                            wallet.public_key
+                        );
+                        return Ok(());
+                    }
+                    FullPolicy::DropOldest => {
+                        let oldest = state.wallets.pop_front();
+                        state.wallets.push_back(wallet);
+                        drop(state);
+                        self.0.dropped.fetch_add(1, Ordering::Relaxed);
+                        if let Some(oldest) = oldest {
+                            warn!(
+                                "Writer queue is full; dropped queued wallet {}",
+                                oldest.public_key
+                            );
+                        }
+                        return Ok(());
+                    }
+                }
+            }
+            popped.await;
+        }
+    }
+
+    /// Wallets the full policy has dropped so far
+    pub fn dropped(&self) -> u64 {
+        self.0.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl Clone for WalletSender {
+    fn clone(&self) -> Self {
+        self.0.state.lock().unwrap().senders += 1;
+        Self(self.0.clone())
+    }
+}
+
+impl Drop for WalletSender {
+    fn drop(&mut self) {
+        let mut state = self.0.state.lock().unwrap();
+        state.senders -= 1;
+        if state.senders == 0 {
+            drop(state);
+            self.0.pushed.notify_one();
+        }
+    }
+}
+
+/// The writer's end of [`wallet_queue`]
+pub struct WalletReceiver(Arc<Queue>);
+
+impl WalletReceiver {
+    /// The next queued wallet, or `None` once the queue is empty and every sender is gone
+    pub async fn recv(&mut self) -> Option<WalletDocument> {
+        loop {
+            {
+                let mut state = self.0.state.lock().unwrap();
+                if let Some(wallet) = state.wallets.pop_front() {
+                    drop(state);
+                    self.0.popped.notify_one();
+                    return Some(wallet);
+                }
+                if state.senders == 0 {
+                    return None;
+                }
+            }
+            // There's only one receiver, so the permit notify_one leaves covers wakeups
+            // that happen before this wait starts
+            self.0.pushed.notified().await;
+        }
+    }
+}
+
+impl Drop for WalletReceiver {
+    fn drop(&mut self) {
+        self.0.state.lock().unwrap().receiver_alive = false;
+        self.0.popped.notify_waiters();
+    }
+}
+
+/// Save a wallet to the sink, retrying with backoff on failure
+pub async fn save_with_retries(
+    config: &Config,
+    sink: &mut dyn WalletSink,
+    wallet_doc: &WalletDocument,
+) -> Result<()> {
+    let save_timeout = tokio::time::Duration::from_secs(config.save_timeout_secs);
+    let mut retry_count = 0;
+
+    loop {
+        // Bound each attempt so a hung sink can't stall the writer forever
+        let result = tokio::time::timeout(save_timeout, sink.save_wallet(wallet_doc))
+            .await
+            .unwrap_or(Err(Error::Timeout(config.save_timeout_secs)));
+
+        match result {
+            Ok(_) => {
+                info!("Wallet successfully saved");
+                return Ok(());
+            }
+            Err(e @ Error::PermanentSave { .. }) => {
+                error!("Save failed permanently, not retrying: {}", e);
+                return Err(e);
+            }
+            Err(e) => {
+                retry_count += 1;
+                if retry_count >= MAX_SAVE_RETRIES {
+                    return Err(e);
+                }
+                let backoff = retry_backoff(retry_count, &mut rand::thread_rng());
+                warn!(
+                    "Save attempt {} failed: {}. Retrying in {} ms...",
+                    retry_count,
+                    e,
+                    backoff.as_millis()
+                );
+                tokio::time::sleep(backoff).await;
+            }
+        }
+    }
+}
+
+/// Local fallback file for wallets the sink failed to save, so a found key
+/// is never lost to a database outage
+struct SpillFile {
+    path: PathBuf,
+    file: FileSink,
+    /// Whether the file holds wallets that haven't been replayed yet
+    pending: bool,
+}
+
+impl SpillFile {
+    fn open(path: &Path) -> Result<Self> {
+        let file = FileSink::new(path)?;
+        let pending = std::fs::metadata(path)?.len() > 0;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            pending,
+        })
+    }
+
+    /// Save every spilled wallet to the sink, keeping only those that still fail
+    async fn replay(&mut self, config: &Config, sink: &mut dyn WalletSink) -> Result<()> {
+        let spilled = read_wallet_file(&self.path)?;
+        let mut remaining = Vec::new();
+
+        for wallet_doc in spilled {
+            if let Err(e) = save_with_retries(config, sink, &wallet_doc).await {
+                warn!(
+                    "Spilled wallet {} still can't be saved: {}",
+                    wallet_doc.public_key, e
+                );
+                remaining.push(wallet_doc);
+            }
+        }
+
+        // Truncate in place: the append handle keeps pointing at the same file
+        let mut writer = BufWriter::new(
+            OpenOptions::new()
+                .write(true)
+                .truncate(true)
+                .open(&self.path)?,
+        );
+        for wallet_doc in &remaining {
+            serde_json::to_writer(&mut writer, wallet_doc)?;
+            writer.write_all(b"\n")?;
+        }
+        writer.flush()?;
+
+        info!(
+            "Replayed spill file {}: {} wallet(s) still pending",
+            self.path.display(),
+            remaining.len()
+        );
+        self.pending = !remaining.is_empty();
+        Ok(())
+    }
+}
+
+/// Owns the sink and saves every wallet the workers find, keeping slow
+/// databases and publishers off the generation hot path
+pub struct WalletWriter {
+    config: Arc<Config>,
+    sink: Box<dyn WalletSink>,
+    spill: Option<SpillFile>,
+    /// Running `--on-found` commands, waited for before exiting
+    hooks: Vec<JoinHandle<()>>,
+}
+
+impl WalletWriter {
+    pub fn new(config: Arc<Config>, sink: Box<dyn WalletSink>) -> Result<Self> {
+        let spill = config
+            .spill_on_db_failure
+            .as_deref()
+            .map(SpillFile::open)
+            .transpose()?;
+
+        Ok(Self {
+            config,
+            sink,
+            spill,
+            hooks: Vec::new(),
+        })
+    }
+
+    /// Replay pending spilled wallets if enabled; failures are logged, not fatal
+    async fn replay_spill(&mut self) {
+        if !self.config.replay_spill {
+            return;
+        }
+        if let Some(spill) = self.spill.as_mut().filter(|spill| spill.pending) {
+            if let Err(e) = spill.replay(&self.config, self.sink.as_mut()).await {
+                error!(
+                    "Failed to replay spill file {}: {}",
+                    spill.path.display(),
+                    e
+                );
+            }
+        }
+    }
+
+    /// Save one wallet, spilling it to the fallback file if every retry fails,
+    /// then start the `--on-found` command for it
+    pub async fn save(&mut self, wallet_doc: &WalletDocument) -> Result<()> {
+        let result = self.store(wallet_doc).await;
+        self.run_hook(wallet_doc);
+        result
+    }
+
+    async fn store(&mut self, wallet_doc: &WalletDocument) -> Result<()> {
+        match save_with_retries(&self.config, self.sink.as_mut(), wallet_doc).await {
+            Ok(()) => {
+                // A successful save means the sink is reachable again
+                self.replay_spill().await;
+                Ok(())
+            }
+            Err(e) => {
+                if matches!(e, Error::PermanentSave { .. }) {
+                    error!(
+                        "Wallet {} was rejected permanently: {}",
+                        wallet_doc.public_key, e
+                    );
+                } else {
+                    error!(
+                        "Failed to save wallet {} after {} attempts: {}",
+                        wallet_doc.public_key, MAX_SAVE_RETRIES, e
+                    );
+                }
+                match &mut self.spill {
+                    Some(spill) => {
+                        spill.file.save_wallet(wallet_doc).await?;
+                        spill.pending = true;
+                        warn!("Wallet spilled to {}", spill.path.display());
+                        Ok(())
+                    }
+                    None => Err(e),
+                }
+            }
+        }
+    }
+
+    /// Start the `--on-found` command without waiting for it, so slow commands
+    /// never hold up saving
+    fn run_hook(&mut self, wallet_doc: &WalletDocument) {
+        let Some(program) = &self.config.on_found else {
+            return;
+        };
+        self.hooks.retain(|hook| !hook.is_finished());
+        match hook::spawn_on_found(program, wallet_doc, self.config.on_found_private_key) {
+            Ok(hook) => self.hooks.push(hook),
+            Err(e) => error!("{:#}", e),
+        }
+    }
+
+    /// Save wallets until every worker has dropped its sender, then flush
+    pub async fn run(mut self, mut wallets: WalletReceiver) {
+        self.replay_spill().await;
+
+        while let Some(wallet_doc) = wallets.recv().await {
+            if let Err(e) = self.save(&wallet_doc).await {
+                error!("Wallet {} was not saved: {}", wallet_doc.public_key, e);
+            }
+        }
+
+        self.finish().await;
+    }
+
+    /// Flush the sink once nothing else can be queued, and wait for running hooks
+    pub async fn finish(mut self) {
+        if let Err(e) = self.sink.flush().await {
+            error!("Failed to flush wallet sink: {}", e);
+        }
+        for hook in self.hooks {
+            if let Err(e) = hook.await {
+                error!("--on-found task terminated with error: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn retry_backoff_stays_under_a_growing_ceiling() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let (mut previous_ceiling, mut previous_mean) = (0, 0);
+        for retry in 1..=10 {
+            let ceiling = (RETRY_BACKOFF_BASE_MS << (retry - 1)).min(RETRY_BACKOFF_MAX_MS);
+            let samples: Vec<u64> = (0..2000)
+                .map(|_| retry_backoff(retry, &mut rng).as_millis() as u64)
+                .collect();
+            assert!(samples.iter().all(|&ms| ms <= ceiling), "retry {retry}");
+
+            // Full jitter averages half the ceiling, so the mean grows until the cap
+            let mean = samples.iter().sum::<u64>() / samples.len() as u64;
+            assert!(
+                mean.abs_diff(ceiling / 2) <= ceiling / 10,
+                "retry {retry}: {mean}"
+            );
+            if ceiling > previous_ceiling {
+                assert!(
+                    mean > previous_mean,
+                    "retry {retry}: {mean} <= {previous_mean}"
+                );
+            }
+            (previous_ceiling, previous_mean) = (ceiling, mean);
+        }
+    }
+
+    #[test]
+    fn retry_backoff_is_capped_for_huge_retry_counts() {
+        let mut rng = StdRng::seed_from_u64(7);
+        for retry in [0, 17, 64, usize::MAX] {
+            let delay = retry_backoff(retry, &mut rng).as_millis() as u64;
+            assert!(delay <= RETRY_BACKOFF_MAX_MS, "retry {retry}: {delay}");
+        }
+    }
+}